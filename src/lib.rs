@@ -0,0 +1,3226 @@
+//! Storage and querying of TODO lists, backed by sqlite.
+//!
+//! The `cltodo` binary is a thin CLI on top of this library.
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, Months, NaiveDate, ParseError};
+use home::home_dir;
+use log::{debug, info};
+use sqlx::{
+    query,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    FromRow, Pool, QueryBuilder, Sqlite, Transaction,
+};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::{
+    cmp::Ordering,
+    env, fmt,
+    fs::{self, create_dir_all},
+    process::Command,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        OnceLock,
+    },
+    time,
+};
+
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+const DB_FOLDER: &str = ".cltodo";
+const DATA_FOLDER: &str = "cltodo";
+const DB_FILE: &str = "data.db";
+const CONFIG_FILE: &str = "config.toml";
+const LISTS_FOLDER: &str = "lists";
+const DB_URL_VAR: &str = "CLTODO_DB_URL";
+const DATE_FORMAT_VAR: &str = "CLTODO_DATE_FORMAT";
+
+/// How long a connection waits for a database locked by another process before failing.
+const BUSY_TIMEOUT_SECS: u64 = 10;
+
+/// Whether dates are stored in UTC instead of the local timezone.
+static UTC_STORAGE: AtomicBool = AtomicBool::new(false);
+
+/// Whether informational messages are silenced.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Format of the dates given on the command line, besides the built-in ones, if any.
+static DATE_FORMAT: OnceLock<Option<String>> = OnceLock::new();
+
+/// Passphrase with which databases are encrypted, if any.
+#[cfg(feature = "sqlcipher")]
+static DB_KEY: OnceLock<String> = OnceLock::new();
+
+/// Schema changes applied on top of the initial tables, in order.
+///
+/// The number of migrations already applied to a database is tracked by its `user_version`,
+/// so new migrations must always be appended to the end of the list.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE todos ADD COLUMN due TEXT",
+    "ALTER TABLE completed_todos ADD COLUMN due TEXT",
+    "ALTER TABLE todos ADD COLUMN tags TEXT NOT NULL DEFAULT ''",
+    "ALTER TABLE completed_todos ADD COLUMN tags TEXT NOT NULL DEFAULT ''",
+    "ALTER TABLE todos ADD COLUMN is_deleted INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE todos ADD COLUMN recurrence TEXT",
+    "ALTER TABLE completed_todos ADD COLUMN recurrence TEXT",
+    "CREATE TABLE operations_log (
+        id INTEGER PRIMARY KEY,
+        operation TEXT NOT NULL,
+        todo_id INTEGER NOT NULL,
+        snapshot TEXT,
+        created_at TEXT NOT NULL
+    ) STRICT",
+    "ALTER TABLE todos ADD COLUMN subtasks TEXT NOT NULL DEFAULT '[]'",
+    "ALTER TABLE completed_todos ADD COLUMN subtasks TEXT NOT NULL DEFAULT '[]'",
+    "ALTER TABLE todos ADD COLUMN updated_at TEXT",
+    "ALTER TABLE todos ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE todos ADD COLUMN blocked_by INTEGER REFERENCES todos (id) ON DELETE SET NULL",
+    "ALTER TABLE todos ADD COLUMN note TEXT",
+    "ALTER TABLE completed_todos ADD COLUMN note TEXT",
+];
+
+/// Errors that can happen while running the application.
+#[derive(Debug)]
+pub enum AppError {
+    /// Error coming from the database.
+    Database(sqlx::Error),
+
+    /// Error coming from the filesystem.
+    Io(io::Error),
+
+    /// The home directory, where the global todo list lives, could not be determined.
+    HomeDirNotFound,
+
+    /// A path could not be represented as valid utf8.
+    InvalidPath(PathBuf),
+
+    /// The entries given for import are malformed.
+    InvalidImport(String),
+
+    /// No text was given for a new entry, neither as argument nor through stdin.
+    MissingText,
+
+    /// A dry run was requested for a command that cannot preview its changes.
+    DryRunUnsupported,
+
+    /// A git reference could not be resolved to a commit, with the reason given by git.
+    UnresolvedCommit(String, String),
+
+    /// The configuration file is malformed.
+    InvalidConfig(String),
+
+    /// No entry with the given id is on the list to block another one.
+    BlockerNotFound(i64),
+
+    /// The given entry is itself blocked, directly or not, by the entry it would block.
+    CyclicBlocker(i64),
+
+    /// An entry was to be moved to the list it is already on.
+    SameList,
+
+    /// No entry with the given id is on the list.
+    EntryNotFound(i64),
+
+    /// No entries with the given ids are on the list, while others given with them were.
+    EntriesNotFound(Vec<i64>),
+
+    /// No entry with the given id is marked as deleted.
+    DeletedEntryNotFound(i64),
+
+    /// The entry with the given id has no subtask at the given position.
+    SubtaskNotFound(i64, usize),
+
+    /// The priority of the given entry is already at the highest or lowest level.
+    PriorityAtLimit(i64, Priority),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::Database(e) => write!(f, "database error: {}", e),
+            AppError::Io(e) => write!(f, "I/O error: {}", e),
+            AppError::HomeDirNotFound => write!(f, "could not determine the home directory"),
+            AppError::InvalidPath(path) => write!(f, "path {:?} is not valid utf8", path),
+            AppError::InvalidImport(e) => write!(f, "invalid import: {}", e),
+            AppError::MissingText => write!(
+                f,
+                "no text given for the entry, pass it as an argument or through stdin"
+            ),
+            AppError::DryRunUnsupported => {
+                write!(f, "--dry-run is not supported by this command")
+            }
+            AppError::UnresolvedCommit(reference, reason) => {
+                write!(f, "could not resolve commit {:?}: {}", reference, reason)
+            }
+            AppError::InvalidConfig(e) => write!(f, "invalid config: {}", e),
+            AppError::BlockerNotFound(id) => write!(f, "no entry found with id {} to block on", id),
+            AppError::CyclicBlocker(id) => write!(
+                f,
+                "entry #{} cannot block an entry it is itself blocked by",
+                id
+            ),
+            AppError::SameList => write!(
+                f,
+                "the source and destination lists are the same database, nothing to move"
+            ),
+            AppError::EntryNotFound(id) => write!(f, "no entry found with id {}", id),
+            AppError::EntriesNotFound(ids) => {
+                let ids: Vec<String> = ids.iter().map(i64::to_string).collect();
+                write!(f, "no entries found with ids {}", ids.join(", "))
+            }
+            AppError::DeletedEntryNotFound(id) => {
+                write!(f, "no deleted entry found with id {}", id)
+            }
+            AppError::SubtaskNotFound(id, index) => {
+                write!(f, "no subtask {} found on entry with id {}", index, id)
+            }
+            AppError::PriorityAtLimit(id, priority) => write!(
+                f,
+                "entry #{} is already {} and cannot be bumped further",
+                id, priority
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::Database(e)
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(e: io::Error) -> Self {
+        AppError::Io(e)
+    }
+}
+
+/// Persistent defaults read from the `config.toml` file of the `~/.cltodo` folder.
+///
+/// Every field is optional, and command-line flags and environment variables take
+/// precedence over them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Priority of new entries.
+    pub priority: Option<Priority>,
+
+    /// Whether datetimes are displayed in extended mode.
+    pub extended: bool,
+
+    /// Name of the todo list used when none is given.
+    pub list: Option<String>,
+
+    /// Colors used to display entries of each priority.
+    pub colors: ColorConfig,
+
+    /// Command run by `check-due` for each entry, with placeholders such as `{text}`.
+    pub notify: Option<String>,
+
+    /// Order in which entries are sorted by priority, from first to last.
+    pub priority_order: Option<Vec<Priority>>,
+
+    /// Format of the dates given on the command line, such as "%d/%m/%Y".
+    pub date_format: Option<String>,
+}
+
+/// Colors of each priority, by the names known by `colored` or "none" for the default
+/// color of the terminal.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    pub critical: Option<String>,
+    pub important: Option<String>,
+    pub normal: Option<String>,
+}
+
+/// Reads the configuration file from the `~/.cltodo` folder.
+///
+/// A missing file, or a missing home directory, stands for the default configuration.
+pub fn read_config() -> Result<Config, AppError> {
+    let Some(home) = home_dir() else {
+        return Ok(Config::default());
+    };
+
+    let path = home.join(DB_FOLDER).join(CONFIG_FILE);
+
+    let content = match fs::read_to_string(&path) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            debug!("No config file found at {}.", path.display());
+            return Ok(Config::default());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    info!("Reading config from {}.", path.display());
+
+    toml::from_str(&content)
+        .map_err(|e| AppError::InvalidConfig(e.to_string().trim_end().to_owned()))
+}
+
+/// Sets whether dates are stored in UTC instead of the local timezone.
+///
+/// Storing in UTC keeps the stored dates consistent when a database is shared between
+/// machines in different timezones. Dates are displayed in local time either way, and
+/// are compared by the instant they represent, so both kinds can live in the same database.
+pub fn set_utc_storage(utc: bool) {
+    UTC_STORAGE.store(utc, AtomicOrdering::Relaxed);
+}
+
+/// Sets whether informational messages, such as notes on which list is used, are silenced.
+///
+/// Results and errors are still printed, as well as warnings about malformed data.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, AtomicOrdering::Relaxed);
+}
+
+/// Whether informational messages are silenced.
+pub fn is_quiet() -> bool {
+    QUIET.load(AtomicOrdering::Relaxed)
+}
+
+/// Sets the passphrase with which databases are encrypted through SQLCipher.
+///
+/// A database created with a passphrase can only be opened with the same one, and a
+/// database created without one cannot be opened with one. Only the first passphrase set is kept.
+#[cfg(feature = "sqlcipher")]
+pub fn set_db_key(key: String) {
+    let _ = DB_KEY.set(key);
+}
+
+/// Sets the format of the dates given on the command line, besides the built-in ones, from
+/// `CLTODO_DATE_FORMAT` or else from the config file, if it could be read.
+///
+/// Dates are parsed along with the arguments, so this must be called before parsing them.
+/// Only the first format set is kept.
+pub fn set_date_format(config: Option<&Config>) {
+    let format = env::var(DATE_FORMAT_VAR)
+        .ok()
+        .filter(|x| !x.is_empty())
+        .or_else(|| config?.date_format.clone());
+
+    let _ = DATE_FORMAT.set(format);
+}
+
+/// Formats a datetime the way it is stored in the database.
+fn to_stored(date: DateTime<Local>) -> String {
+    if UTC_STORAGE.load(AtomicOrdering::Relaxed) {
+        let utc = FixedOffset::east_opt(0).expect("Zero should be a valid offset.");
+        date.with_timezone(&utc).to_string()
+    } else {
+        date.to_string()
+    }
+}
+
+/// Filters shared by the commands that query TODO entries.
+#[derive(Args, Default)]
+pub struct Filters {
+    /// Filters by entries with the given priority. Can be given multiple times, or as a
+    /// comma-separated list, to match any of them.
+    #[arg(short, long, value_delimiter = ',')]
+    pub priority: Vec<Priority>,
+
+    /// Filters by entries with at least the given priority.
+    #[arg(long, conflicts_with = "priority")]
+    pub priority_min: Option<Priority>,
+
+    /// Filters by entries added at or after the given datetime. Inclusive, unless `--strict`.
+    #[arg(
+        short,
+        long,
+        visible_alias = "after",
+        value_parser = to_datetime_from,
+        allow_hyphen_values = true
+    )]
+    pub from: Option<DateTime<Local>>,
+
+    /// Filters by entries added at or before the given datetime. Inclusive, unless `--strict`.
+    #[arg(
+        short,
+        long,
+        visible_alias = "before",
+        value_parser = to_datetime_to,
+        allow_hyphen_values = true
+    )]
+    pub to: Option<DateTime<Local>>,
+
+    /// Makes the `--from` and `--to` bounds exclusive. A date without a time still stands for
+    /// its whole day, i.e. from its first to its last second, so only those instants are left out.
+    #[arg(long, default_value_t = false)]
+    pub strict: bool,
+
+    /// Filters by entries carrying the given tag.
+    #[arg(long, value_parser = to_tag)]
+    pub tag: Option<String>,
+
+    /// Filters by entries that are not blocked by another entry still on the list.
+    #[arg(long, default_value_t = false)]
+    pub ready: bool,
+}
+
+/// Validates the text of an entry, which is trimmed and cannot be blank.
+pub fn to_text(s: &str) -> Result<String, String> {
+    let text = s.trim();
+
+    if text.is_empty() {
+        Err("Texts cannot be empty.".to_string())
+    } else {
+        Ok(text.to_string())
+    }
+}
+
+/// Validates a tag, which must be non-empty and cannot contain commas.
+pub fn to_tag(s: &str) -> Result<String, String> {
+    let tag = s.trim();
+
+    if tag.is_empty() {
+        Err("Tags cannot be empty.".to_string())
+    } else if tag.contains(',') {
+        Err("Tags cannot contain commas.".to_string())
+    } else {
+        Ok(tag.to_string())
+    }
+}
+
+/// Transforms string to datetime.
+///
+/// If string is in date format, then sets hours, mins and secs to 0.
+pub fn to_datetime_from(s: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(x) = DateTime::from_str(s) {
+        Ok(x)
+    } else if let Some(x) = parse_date(s) {
+        Ok(start_of_day(x))
+    } else {
+        Err("Invalid input for date/datetime.".to_string())
+    }
+}
+
+/// Transforms string to datetime.
+///
+/// If string is in date format, then sets hours, mins and secs to 23, 59 and 59
+/// respectively, i.e. to the end of the day.
+pub fn to_datetime_to(s: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(x) = DateTime::from_str(s) {
+        Ok(x)
+    } else if let Some(x) = parse_date(s) {
+        Ok(end_of_day(x))
+    } else {
+        Err("Invalid input for date/datetime.".to_string())
+    }
+}
+
+/// Returns the local datetime at the start of the given day, i.e. with hours, mins and secs set to 0.
+pub fn start_of_day(date: NaiveDate) -> DateTime<Local> {
+    let date_with_hms = date
+        .and_hms_opt(0, 0, 0)
+        .expect("All zeroes should be valid inputs.");
+    date_with_hms.and_local_timezone(Local).unwrap()
+}
+
+/// Returns the local datetime at the end of the given day, i.e. with hours, mins and secs
+/// set to 23, 59 and 59 respectively.
+pub fn end_of_day(date: NaiveDate) -> DateTime<Local> {
+    let date_with_hms = date
+        .and_hms_opt(23, 59, 59)
+        .expect("23, 59, 59 should be valid inputs.");
+    date_with_hms.and_local_timezone(Local).unwrap()
+}
+
+/// Parses a date in any of the accepted formats.
+///
+/// These are relative dates, dates in the format set by the user, ISO dates (i.e., YYYY-MM-DD)
+/// and short dates without the year.
+pub fn parse_date(s: &str) -> Option<NaiveDate> {
+    let today = Local::now().date_naive();
+
+    parse_relative_date(s)
+        .or_else(|| NaiveDate::parse_from_str(s, date_format()?).ok())
+        .or_else(|| NaiveDate::from_str(s).ok())
+        .or_else(|| parse_short_date(s, today))
+}
+
+/// Format of the dates given on the command line, as set by `set_date_format`.
+fn date_format() -> Option<&'static str> {
+    DATE_FORMAT.get()?.as_deref()
+}
+
+/// Parses a date without the year, either as MM-DD or as DD/MM.
+///
+/// The year is assumed to be the one of the given day of reference, unless that would
+/// place the date more than six months in its future, in which case the previous year is used.
+pub fn parse_short_date(s: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let this_year = today.year();
+    let six_months_ahead = today.checked_add_months(Months::new(6))?;
+
+    [this_year, this_year - 1]
+        .iter()
+        .filter_map(|year| {
+            NaiveDate::parse_from_str(&format!("{}-{}", year, s), "%Y-%m-%d")
+                .or_else(|_| NaiveDate::parse_from_str(&format!("{}/{}", s, year), "%d/%m/%Y"))
+                .ok()
+        })
+        .find(|x| *x <= six_months_ahead)
+}
+
+/// Parses a date relative to today.
+///
+/// Accepts the keywords "today", "yesterday" and "tomorrow", as well as offsets
+/// in days, weeks or months such as "-7d", "-2w" or "+1m".
+pub fn parse_relative_date(s: &str) -> Option<NaiveDate> {
+    let today = Local::now().date_naive();
+
+    match s {
+        "today" => return Some(today),
+        "yesterday" => return today.pred_opt(),
+        "tomorrow" => return today.succ_opt(),
+        _ => (),
+    }
+
+    let (sign, rest) = match s.chars().next()? {
+        '-' => (-1, &s[1..]),
+        '+' => (1, &s[1..]),
+        _ => return None,
+    };
+
+    let unit = rest.chars().last()?;
+    let amount: u32 = rest[..rest.len() - unit.len_utf8()].parse().ok()?;
+
+    match (unit, sign) {
+        ('d', _) => today.checked_add_signed(Duration::days(sign * i64::from(amount))),
+        ('w', _) => today.checked_add_signed(Duration::weeks(sign * i64::from(amount))),
+        ('m', 1) => today.checked_add_months(Months::new(amount)),
+        ('m', _) => today.checked_sub_months(Months::new(amount)),
+        _ => None,
+    }
+}
+
+/// Gets the date of the commit a git reference points to, such as a tag or a branch.
+///
+/// The reference is resolved by the repository of the current directory.
+pub fn commit_date(reference: &str) -> Result<DateTime<Local>, AppError> {
+    let unresolved = |reason: &str| AppError::UnresolvedCommit(reference.to_owned(), reason.into());
+
+    let output = Command::new("git")
+        .arg("show")
+        .arg("-s")
+        .arg("--format=%cI")
+        .arg(format!("{}^{{commit}}", reference))
+        .arg("--")
+        .output()
+        .map_err(|e| unresolved(&format!("could not run git: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(unresolved(
+            stderr.lines().next().unwrap_or("unknown git error"),
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    DateTime::parse_from_rfc3339(stdout.trim())
+        .map(|x| x.with_timezone(&Local))
+        .map_err(|e| unresolved(&format!("invalid commit date: {}", e)))
+}
+
+/// Datetimes that can be displayed in either short or extended mode.
+pub trait Extendable {
+    fn get_style(&self, extended: bool) -> String;
+}
+
+impl Extendable for DateTime<Local> {
+    /// Prints extended or non-extended mode.
+    ///
+    /// Extended mode consists of entire ISO timestamp, whereas non-extended
+    /// consists of only the date (i.e., YYYY-MM-DD).
+    fn get_style(&self, extended: bool) -> String {
+        if extended {
+            self.to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+        } else {
+            self.date_naive().to_string()
+        }
+    }
+}
+
+/// Datetimes that can be displayed relative to another one, e.g. as "3d ago".
+pub trait Relative {
+    fn get_relative(&self, now: DateTime<Local>) -> String;
+}
+
+impl Relative for DateTime<Local> {
+    /// Prints the time elapsed since the datetime, or until it if in the future, in its
+    /// largest whole unit among minutes, hours and days. Less than a minute is "just now".
+    fn get_relative(&self, now: DateTime<Local>) -> String {
+        let elapsed = now.signed_duration_since(*self);
+        let seconds = elapsed.num_seconds().abs();
+
+        let amount = match seconds {
+            0..=59 => return "just now".to_string(),
+            60..=3599 => format!("{}m", seconds / 60),
+            3600..=86399 => format!("{}h", seconds / 3600),
+            _ => format!("{}d", seconds / 86400),
+        };
+
+        if elapsed < Duration::zero() {
+            format!("in {}", amount)
+        } else {
+            format!("{} ago", amount)
+        }
+    }
+}
+
+/// Priority of a TODO entry.
+///
+/// On the command line, it can be given either by name or by its value.
+#[derive(Debug, ValueEnum, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[value(alias = "0")]
+    Normal = 0,
+    #[value(alias = "1")]
+    Important = 1,
+    #[value(alias = "2")]
+    Critical = 2,
+}
+
+impl Priority {
+    pub fn from_i64(i: i64) -> Option<Self> {
+        match i {
+            0 => Some(Priority::Normal),
+            1 => Some(Priority::Important),
+            2 => Some(Priority::Critical),
+            _ => None,
+        }
+    }
+
+    pub fn to_i64(&self) -> i64 {
+        self.clone() as i64
+    }
+
+    /// Abbreviated name of the priority.
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            Priority::Normal => "NORM",
+            Priority::Important => "IMP",
+            Priority::Critical => "CRIT",
+        }
+    }
+
+    /// Position of the priority when sorting in the given order, with lower ones coming first.
+    ///
+    /// Priorities left out of the order come after the given ones, highest first. In
+    /// particular, highest priorities come first when the order is empty.
+    fn rank(&self, order: &[Priority]) -> usize {
+        let default = (Priority::Critical.to_i64() - self.to_i64()) as usize;
+
+        order
+            .iter()
+            .position(|x| x.to_i64() == self.to_i64())
+            .unwrap_or(order.len() + default)
+    }
+
+    /// Short symbol standing for the priority, with more marks for higher priorities.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Priority::Normal => "!",
+            Priority::Important => "!!",
+            Priority::Critical => "!!!",
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Priority::Normal => f.pad("NORMAL"),
+            Priority::Important => f.pad("IMPORTANT"),
+            Priority::Critical => f.pad("CRITICAL"),
+        }
+    }
+}
+
+/// How often a TODO entry repeats.
+#[derive(Debug, ValueEnum, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Recurrence {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "daily" => Some(Recurrence::Daily),
+            "weekly" => Some(Recurrence::Weekly),
+            "monthly" => Some(Recurrence::Monthly),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Recurrence::Daily => "daily",
+            Recurrence::Weekly => "weekly",
+            Recurrence::Monthly => "monthly",
+        }
+    }
+
+    /// Advances the datetime by the given number of periods.
+    ///
+    /// Months are always counted from the original datetime, so that a date at the
+    /// end of a month does not drift when going through shorter months.
+    fn advance(&self, date: DateTime<Local>, periods: u32) -> Option<DateTime<Local>> {
+        match self {
+            Recurrence::Daily => date.checked_add_signed(Duration::days(periods.into())),
+            Recurrence::Weekly => date.checked_add_signed(Duration::weeks(periods.into())),
+            Recurrence::Monthly => date.checked_add_months(Months::new(periods)),
+        }
+    }
+
+    /// Next due date of an entry repeating with this rule.
+    ///
+    /// This is the first datetime after `now` that is a whole number of periods after
+    /// the previous due date. Entries without a due date are taken as due on `now`, so
+    /// that the next one is due exactly one period after it.
+    pub fn next_due(
+        &self,
+        due: Option<DateTime<Local>>,
+        now: DateTime<Local>,
+    ) -> Option<DateTime<Local>> {
+        let due = due.unwrap_or(now);
+
+        (1..)
+            .map(|n| self.advance(due, n))
+            .find(|x| x.is_none_or(|x| x > now))?
+    }
+}
+
+/// Columns selected from each table of entries, so that they can be queried together.
+const ACTIVE_SOURCE: &str = "SELECT
+    id, date, text, priority, due, tags, recurrence, is_deleted, subtasks, updated_at, pinned,
+    blocked_by, note, 'active' AS status
+FROM todos";
+
+const DONE_SOURCE: &str = "SELECT
+    id, date, text, priority, due, tags, recurrence, 0 AS is_deleted, subtasks,
+    completed_at AS updated_at, 0 AS pinned, NULL AS blocked_by, note, 'done' AS status
+FROM completed_todos";
+
+const ARCHIVED_SOURCE: &str = "SELECT
+    id, date, text, priority, due, tags, recurrence, is_deleted, subtasks,
+    archived_at AS updated_at, 0 AS pinned, NULL AS blocked_by, note, 'archived' AS status
+FROM archived_todos";
+
+/// Status of a TODO entry, given by the table it is stored in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    #[default]
+    Active,
+    Done,
+    Archived,
+}
+
+/// Statuses of the entries a query looks into.
+#[derive(Debug, ValueEnum, Clone, Default, PartialEq)]
+pub enum Scope {
+    /// Entries still on the list.
+    #[default]
+    Active,
+
+    /// Entries marked as done.
+    Done,
+
+    /// Entries moved away by `archive`.
+    Archived,
+
+    /// Entries of any status.
+    All,
+}
+
+/// Field by which entries are sorted.
+#[derive(Debug, ValueEnum, Clone, Default)]
+pub enum SortField {
+    /// Most recent entries first.
+    Date,
+
+    /// Highest priorities first, or as set by `--priority-order`, then most recent entries first.
+    #[default]
+    Priority,
+
+    /// Shortest texts first.
+    Length,
+
+    /// Texts in alphabetical order, case-insensitively.
+    Text,
+
+    /// Most recently modified entries first.
+    Modified,
+}
+
+impl SortField {
+    /// Compares two entries on this field only, with priorities in the given order.
+    ///
+    /// Entries are expected to be already ordered by date, so sorting by date
+    /// does nothing and ties are kept in chronological order.
+    fn compare(&self, a: &Todo, b: &Todo, priority_order: &[Priority]) -> Ordering {
+        match self {
+            SortField::Date => Ordering::Equal,
+            SortField::Priority => a
+                .priority
+                .rank(priority_order)
+                .cmp(&b.priority.rank(priority_order)),
+            SortField::Length => a.text.chars().count().cmp(&b.text.chars().count()),
+            SortField::Text => a.text.to_lowercase().cmp(&b.text.to_lowercase()),
+            SortField::Modified => b.updated_at.cmp(&a.updated_at),
+        }
+    }
+}
+
+/// TODO entry as stored in the database.
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+struct TodoEntry {
+    id: i64,
+    date: String,
+    text: String,
+    priority: i64,
+    due: Option<String>,
+    tags: String,
+    recurrence: Option<String>,
+    is_deleted: i64,
+    subtasks: String,
+    #[serde(default)]
+    updated_at: Option<String>,
+    #[serde(default)]
+    pinned: i64,
+    #[serde(default)]
+    blocked_by: Option<i64>,
+    #[serde(default)]
+    note: Option<String>,
+    #[sqlx(default)]
+    #[serde(default)]
+    status: Option<String>,
+}
+
+impl TodoEntry {
+    /// Priority of the entry, falling back to normal with a warning if the stored value
+    /// is out of range, e.g. when written by a newer version or edited by hand.
+    fn priority(&self) -> Priority {
+        Priority::from_i64(self.priority).unwrap_or_else(|| {
+            eprintln!(
+                "Warning: entry #{} has invalid priority {}, treating it as normal.",
+                self.id, self.priority
+            );
+            Priority::Normal
+        })
+    }
+
+    /// Subtasks of the entry, as stored in the database.
+    fn subtasks(&self) -> Result<Vec<Subtask>, EntryError> {
+        serde_json::from_str(&self.subtasks).map_err(EntryError::Subtasks)
+    }
+}
+
+/// Step of a TODO entry that can be checked on its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subtask {
+    pub text: String,
+    pub done: bool,
+}
+
+/// TODO entry of a list.
+#[derive(Debug, Clone, Serialize)]
+pub struct Todo {
+    pub id: i64,
+    pub date: DateTime<Local>,
+    pub text: String,
+    pub priority: Priority,
+    pub due: Option<DateTime<Local>>,
+    pub tags: Vec<String>,
+    pub recurrence: Option<Recurrence>,
+    pub subtasks: Vec<Subtask>,
+    pub updated_at: DateTime<Local>,
+    pub pinned: bool,
+
+    /// Id of the entry that must be done before this one, if it is still on the list.
+    pub blocked_by: Option<i64>,
+
+    /// Longer description of the entry, kept apart from its text.
+    pub note: Option<String>,
+
+    pub status: Status,
+}
+
+/// TODO as given for import. Fields other than these, such as the id, are ignored.
+#[derive(Debug, Deserialize)]
+pub struct ImportedTodo {
+    text: String,
+    priority: Priority,
+    date: Option<DateTime<Local>>,
+    due: Option<DateTime<Local>>,
+    #[serde(default)]
+    tags: Vec<String>,
+    recurrence: Option<Recurrence>,
+    #[serde(default)]
+    subtasks: Vec<Subtask>,
+    #[serde(default)]
+    pinned: bool,
+    note: Option<String>,
+}
+
+/// Reason why a database row cannot be read as a TODO.
+#[derive(Debug)]
+pub enum EntryError {
+    /// One of its dates is malformed.
+    Date(ParseError),
+
+    /// Its subtasks are not valid JSON.
+    Subtasks(serde_json::Error),
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntryError::Date(e) => write!(f, "invalid date: {}", e),
+            EntryError::Subtasks(e) => write!(f, "invalid subtasks: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EntryError {}
+
+impl From<ParseError> for EntryError {
+    fn from(e: ParseError) -> Self {
+        EntryError::Date(e)
+    }
+}
+
+impl From<EntryError> for sqlx::Error {
+    fn from(e: EntryError) -> Self {
+        sqlx::Error::Decode(Box::new(e))
+    }
+}
+
+impl Todo {
+    /// Transforms TodoEntry into Todo.
+    fn from_entry(entry: &TodoEntry) -> Result<Self, EntryError> {
+        let date = DateTime::from_str(&entry.date)?;
+
+        Ok(Todo {
+            id: entry.id,
+            date,
+            text: entry.text.to_owned(),
+            priority: entry.priority(),
+            due: entry.due.as_deref().map(DateTime::from_str).transpose()?,
+            tags: entry
+                .tags
+                .split(',')
+                .filter(|x| !x.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            recurrence: entry.recurrence.as_deref().and_then(Recurrence::from_name),
+            subtasks: entry.subtasks()?,
+            updated_at: entry
+                .updated_at
+                .as_deref()
+                .map(DateTime::from_str)
+                .transpose()?
+                .unwrap_or(date),
+            pinned: entry.pinned != 0,
+            blocked_by: entry.blocked_by,
+            note: entry.note.clone(),
+            status: match entry.status.as_deref() {
+                Some("done") => Status::Done,
+                Some("archived") => Status::Archived,
+                _ => Status::Active,
+            },
+        })
+    }
+
+    /// Whether the TODO is past its due date.
+    pub fn is_overdue(&self) -> bool {
+        self.due.is_some_and(|due| due < Local::now())
+    }
+}
+
+/// Posts new TODO into database.
+pub async fn post_todo(
+    text: &str,
+    pool: &Pool<Sqlite>,
+    priority: Priority,
+    due: Option<DateTime<Local>>,
+    tags: &[String],
+    recurrence: Option<Recurrence>,
+    subtasks: &[String],
+) -> Result<(), sqlx::Error> {
+    post_todos(
+        &[text.to_owned()],
+        pool,
+        None,
+        priority,
+        due,
+        tags,
+        recurrence,
+        subtasks,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Posts a new TODO into database for each of the texts, all sharing the other fields.
+/// Texts are stored without surrounding whitespace.
+///
+/// They are dated with the given date, e.g. when backfilling past tasks, or else with the
+/// current one. Everything happens in a single transaction, so that either all or none
+/// are stored. Each addition is logged on its own, so that they are undone one at a time.
+///
+/// The blocker, if any, is expected to be checked beforehand through `check_blocker`.
+#[allow(clippy::too_many_arguments)]
+pub async fn post_todos(
+    texts: &[String],
+    pool: &Pool<Sqlite>,
+    date: Option<DateTime<Local>>,
+    priority: Priority,
+    due: Option<DateTime<Local>>,
+    tags: &[String],
+    recurrence: Option<Recurrence>,
+    subtasks: &[String],
+    blocked_by: Option<i64>,
+    note: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let date = date.unwrap_or_else(|| DateTime::<Local>::from(time::SystemTime::now()));
+    let to_store = to_stored(date);
+    let priority = priority as i64;
+    let due = due.map(to_stored);
+    let tags = tags.join(",");
+    let recurrence = recurrence.map(|x| x.name());
+    let subtasks: Vec<Subtask> = subtasks
+        .iter()
+        .map(|x| Subtask {
+            text: x.to_owned(),
+            done: false,
+        })
+        .collect();
+    let subtasks = to_json(&subtasks);
+
+    let mut transaction = pool.begin().await?;
+
+    for text in texts {
+        let text = text.trim();
+
+        let oi = sqlx::query!(
+            "INSERT INTO todos (
+                date, text, priority, due, tags, recurrence, subtasks, updated_at, blocked_by, note
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            to_store,
+            text,
+            priority,
+            due,
+            tags,
+            recurrence,
+            subtasks,
+            to_store,
+            blocked_by,
+            note
+        );
+
+        let id = oi.execute(&mut transaction).await?.last_insert_rowid();
+
+        log_operation(&mut transaction, Operation::Add, id, None).await?;
+    }
+
+    transaction.commit().await
+}
+
+/// Gets the id of the oldest entry with exactly the given text, if any.
+///
+/// If `since` is given, only entries added from then on are considered.
+pub async fn find_duplicate(
+    text: &str,
+    since: Option<DateTime<Local>>,
+    pool: &Pool<Sqlite>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut query = QueryBuilder::new("SELECT id FROM todos WHERE is_deleted = 0 AND text = ");
+    query.push_bind(text);
+
+    if let Some(x) = since {
+        query.push(" AND julianday(date) >= julianday(");
+        query.push_bind(to_stored(x));
+        query.push(")");
+    }
+
+    query.push(" ORDER BY id LIMIT 1");
+
+    let id: Option<(i64,)> = query.build_query_as().fetch_optional(pool).await?;
+
+    Ok(id.map(|(x,)| x))
+}
+
+/// Reads and validates TODOs to import from a JSON file, or from stdin if the path is "-".
+pub fn read_import(file: &Path) -> Result<Vec<ImportedTodo>, AppError> {
+    let content = if file == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(file)?
+    };
+
+    parse_import(&content)
+}
+
+/// Parses the entries of a JSON array to import, whose texts and tags follow the same rules
+/// as those given through the CLI.
+fn parse_import(content: &str) -> Result<Vec<ImportedTodo>, AppError> {
+    let values: Vec<serde_json::Value> = serde_json::from_str(content)
+        .map_err(|e| AppError::InvalidImport(format!("expected a JSON array: {}", e)))?;
+
+    let parse = |x: serde_json::Value| -> Result<ImportedTodo, String> {
+        let mut todo: ImportedTodo = serde_json::from_value(x).map_err(|e| e.to_string())?;
+
+        todo.text = to_text(&todo.text)?;
+        todo.tags = todo
+            .tags
+            .iter()
+            .map(|x| to_tag(x))
+            .collect::<Result<_, _>>()?;
+
+        Ok(todo)
+    };
+
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, x)| {
+            parse(x).map_err(|e| AppError::InvalidImport(format!("entry at index {}: {}", i, e)))
+        })
+        .collect()
+}
+
+/// Posts TODOs into database in a single transaction, so that either all or none are stored.
+///
+/// Entries without a date are stored with the current one.
+pub async fn import_todos(
+    todos: Vec<ImportedTodo>,
+    pool: &Pool<Sqlite>,
+) -> Result<(), sqlx::Error> {
+    let now = DateTime::<Local>::from(time::SystemTime::now());
+
+    let mut transaction = pool.begin().await?;
+
+    for todo in todos {
+        let to_store = to_stored(todo.date.unwrap_or(now));
+        let priority = todo.priority as i64;
+        let due = todo.due.map(to_stored);
+        let tags = todo.tags.join(",");
+        let recurrence = todo.recurrence.map(|x| x.name());
+        let subtasks = to_json(&todo.subtasks);
+        let pinned = i64::from(todo.pinned);
+
+        let q = query!(
+            "INSERT INTO todos
+                (date, text, priority, due, tags, recurrence, subtasks, updated_at, pinned, note)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            to_store,
+            todo.text,
+            priority,
+            due,
+            tags,
+            recurrence,
+            subtasks,
+            to_store,
+            pinned,
+            todo.note
+        );
+
+        let id = q.execute(&mut transaction).await?.last_insert_rowid();
+
+        forget_operations(&mut transaction, id).await?;
+    }
+
+    transaction.commit().await
+}
+
+/// Runs a query selecting whole rows of the todos table and converts them into Todos.
+async fn fetch_todos(
+    mut query: QueryBuilder<'_, Sqlite>,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<Todo>, sqlx::Error> {
+    let query = query.build();
+
+    let entries: Vec<TodoEntry> = query
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|x| TodoEntry::from_row(x).expect("Database entries should always be convertible."))
+        .collect();
+
+    // Blockers that were done or purged are unset by the database, but deleted ones are not.
+    let blockers: Vec<(i64,)> = sqlx::query_as(
+        "SELECT id FROM todos WHERE is_deleted = 0 AND id IN (SELECT blocked_by FROM todos)",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    // A row that cannot be read is skipped, so that it does not make the whole list unreadable.
+    Ok(entries
+        .iter()
+        .filter_map(|x| match Todo::from_entry(x) {
+            Ok(mut todo) => {
+                todo.blocked_by = todo.blocked_by.filter(|x| blockers.contains(&(*x,)));
+                Some(todo)
+            }
+            Err(e) => {
+                eprintln!("Warning: skipping unreadable entry #{}: {}.", x.id, e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Pushes the filtering conditions shared by queries over the TODO list.
+///
+/// Expects the query to already contain a `WHERE` clause.
+fn push_filters(query: &mut QueryBuilder<Sqlite>, filters: &Filters) {
+    if !filters.priority.is_empty() {
+        query.push(" AND priority IN (");
+
+        let mut priorities = query.separated(", ");
+        for x in &filters.priority {
+            priorities.push_bind(x.to_i64());
+        }
+
+        query.push(")");
+    }
+
+    if let Some(x) = &filters.priority_min {
+        query.push(" AND priority >= ");
+        query.push_bind(x.to_i64());
+    }
+
+    let (lower, upper) = if filters.strict {
+        (">", "<")
+    } else {
+        (">=", "<=")
+    };
+
+    // Dates are compared as instants, since they may be stored with different offsets.
+    if let Some(x) = filters.from {
+        query.push(format!(" AND julianday(date) {} julianday(", lower));
+        query.push_bind(to_stored(x));
+        query.push(")");
+    }
+
+    if let Some(x) = filters.to {
+        query.push(format!(" AND julianday(date) {} julianday(", upper));
+        query.push_bind(to_stored(x));
+        query.push(")");
+    }
+
+    if let Some(x) = &filters.tag {
+        query.push(" AND ',' || tags || ',' LIKE '%,' || ");
+        query.push_bind(escape_like(x));
+        query.push(" || ',%' ESCAPE '\\'");
+    }
+
+    // Deleted blockers are kept on the table, so their rows must be checked as well.
+    if filters.ready {
+        query.push(
+            " AND (blocked_by IS NULL OR blocked_by NOT IN (SELECT id FROM todos WHERE is_deleted = 0))",
+        );
+    }
+}
+
+/// Gets entries from TODO list according to parameters selected.
+///
+/// Entries sorted by priority follow the given order of priorities, highest first when
+/// empty. Reversing flips the order of length, text and modification sorts, whereas for date
+/// and priority sorts it only flips the chronological order. In particular, entries
+/// sorted by priority are still grouped in the same order of priorities, each
+/// group keeping the requested chronological direction.
+pub async fn get_entries(
+    filters: &Filters,
+    reversed: bool,
+    sort: &SortField,
+    priority_order: &[Priority],
+    limit: Option<usize>,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<Todo>, sqlx::Error> {
+    get_entries_by_status(
+        &Scope::Active,
+        filters,
+        reversed,
+        sort,
+        priority_order,
+        limit,
+        pool,
+    )
+    .await
+}
+
+/// Gets entries with the given statuses according to parameters selected, as `get_entries`.
+///
+/// Done and archived entries are read from their own tables, and are neither pinned nor blocked.
+pub async fn get_entries_by_status(
+    scope: &Scope,
+    filters: &Filters,
+    reversed: bool,
+    sort: &SortField,
+    priority_order: &[Priority],
+    limit: Option<usize>,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<Todo>, sqlx::Error> {
+    let mut query = match scope {
+        Scope::Active => QueryBuilder::new("SELECT * from todos WHERE is_deleted = 0"),
+        _ => {
+            let archived: (bool,) = sqlx::query_as(
+                "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'archived_todos')",
+            )
+            .fetch_one(pool)
+            .await?;
+
+            let sources: Vec<&str> = [
+                (Scope::Active, ACTIVE_SOURCE),
+                (Scope::Done, DONE_SOURCE),
+                (Scope::Archived, ARCHIVED_SOURCE),
+            ]
+            .into_iter()
+            .filter(|(x, _)| matches!(scope, Scope::All) || x == scope)
+            .filter(|(x, _)| archived.0 || *x != Scope::Archived)
+            .map(|(_, source)| source)
+            .collect();
+
+            // Without the archived table, there may be nothing to select from at all.
+            if sources.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let mut query = QueryBuilder::new("SELECT * FROM (");
+            query.push(sources.join(" UNION ALL "));
+            query.push(") WHERE is_deleted = 0");
+            query
+        }
+    };
+
+    push_filters(&mut query, filters);
+
+    // Pinned entries are also put first here, so that a pushed limit keeps them.
+    // Entries with the same date, e.g. imported together, are ordered by id to keep the output stable.
+    if reversed {
+        query.push(" ORDER BY pinned DESC, julianday(date) ASC, id ASC");
+    } else {
+        query.push(" ORDER BY pinned DESC, julianday(date) DESC, id DESC");
+    }
+
+    // Other sorts happen after the query, so the limit can only be pushed
+    // to the database when the order is purely chronological.
+    if let (Some(x), SortField::Date) = (limit, sort) {
+        query.push(" LIMIT ");
+        query.push_bind(x as i64);
+    }
+
+    let mut todos = fetch_todos(query, pool).await?;
+
+    // The sort is stable, so entries that compare equal keep their chronological order.
+    // Pinned entries always come first, whatever the field or direction.
+    todos.sort_by(|a, b| {
+        let ordering = sort.compare(a, b, priority_order);
+
+        let ordering = if reversed
+            && matches!(
+                sort,
+                SortField::Length | SortField::Text | SortField::Modified
+            ) {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+
+        b.pinned.cmp(&a.pinned).then(ordering)
+    });
+
+    if let Some(x) = limit {
+        todos.truncate(x);
+    }
+
+    Ok(todos)
+}
+
+/// Gets entries from TODO list that are due up to the given datetime, inclusive, soonest first.
+pub async fn get_due_entries(
+    until: DateTime<Local>,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<Todo>, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        "SELECT * from todos WHERE is_deleted = 0 AND due IS NOT NULL AND julianday(due) <= julianday(",
+    );
+    query.push_bind(to_stored(until));
+    query.push(") ORDER BY julianday(due) ASC");
+
+    fetch_todos(query, pool).await
+}
+
+/// Gets entries from TODO list whose text contains the given term, case-insensitively.
+///
+/// Wildcards of `LIKE` are escaped, so that the term is matched literally.
+pub async fn search_entries(
+    term: &str,
+    reversed: bool,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<Todo>, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        "SELECT * from todos WHERE is_deleted = 0 AND LOWER(text) LIKE '%' || LOWER(",
+    );
+    query.push_bind(escape_like(term));
+    query.push(") || '%' ESCAPE '\\'");
+
+    if reversed {
+        query.push(" ORDER BY julianday(date) ASC, id ASC");
+    } else {
+        query.push(" ORDER BY julianday(date) DESC, id DESC");
+    }
+
+    fetch_todos(query, pool).await
+}
+
+/// Escapes the wildcards of `LIKE` patterns, using backslash as the escape character.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Counts entries from TODO list according to parameters selected, grouped by priority.
+///
+/// Returns the counts indexed by priority value.
+pub async fn count_entries(
+    filters: &Filters,
+    pool: &Pool<Sqlite>,
+) -> Result<[i64; 3], sqlx::Error> {
+    let mut query = QueryBuilder::new("SELECT priority, COUNT(*) from todos WHERE is_deleted = 0");
+
+    push_filters(&mut query, filters);
+
+    query.push(" GROUP BY priority");
+
+    let rows: Vec<(i64, i64)> = query.build_query_as().fetch_all(pool).await?;
+
+    let mut counts = [0; 3];
+    for (priority, count) in rows {
+        if let Some(x) = Priority::from_i64(priority) {
+            counts[x as usize] = count;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Summary of a TODO list.
+pub struct Stats {
+    /// Number of entries, indexed by priority value.
+    pub counts: [i64; 3],
+
+    /// Date of the oldest entry, if any.
+    pub oldest: Option<DateTime<Local>>,
+
+    /// Date of the newest entry, if any.
+    pub newest: Option<DateTime<Local>>,
+
+    /// Number of entries added over the last `STATS_DAYS` days.
+    pub recently_added: i64,
+}
+
+/// Number of days considered for the recent activity of the stats.
+pub const STATS_DAYS: i64 = 30;
+
+/// Gathers a summary of the entries of the database.
+pub async fn get_stats(pool: &Pool<Sqlite>) -> Result<Stats, sqlx::Error> {
+    let counts = count_entries(&Filters::default(), pool).await?;
+
+    let (oldest, newest): (Option<String>, Option<String>) = sqlx::query_as(
+        "SELECT
+            (SELECT date FROM todos WHERE is_deleted = 0 ORDER BY julianday(date) ASC LIMIT 1),
+            (SELECT date FROM todos WHERE is_deleted = 0 ORDER BY julianday(date) DESC LIMIT 1)",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let parse = |x: Option<String>| x.and_then(|x| DateTime::from_str(&x).ok());
+
+    let since = Local::now() - Duration::days(STATS_DAYS);
+    let (recently_added,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM todos WHERE is_deleted = 0 AND julianday(date) >= julianday(?)",
+    )
+    .bind(to_stored(since))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Stats {
+        counts,
+        oldest: parse(oldest),
+        newest: parse(newest),
+        recently_added,
+    })
+}
+
+/// Counts the entries added on each day between the given datetimes, inclusive.
+///
+/// Days are taken in local time, and every day of the range is present, in order,
+/// even if no entry was added on it.
+pub async fn count_by_day(
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<(NaiveDate, i64)>, sqlx::Error> {
+    let dates: Vec<(String,)> = sqlx::query_as(
+        "SELECT date FROM todos
+        WHERE is_deleted = 0 AND julianday(date) >= julianday(?) AND julianday(date) <= julianday(?)",
+    )
+    .bind(to_stored(from))
+    .bind(to_stored(to))
+    .fetch_all(pool)
+    .await?;
+
+    let mut counts: Vec<(NaiveDate, i64)> = from
+        .date_naive()
+        .iter_days()
+        .take_while(|x| *x <= to.date_naive())
+        .map(|x| (x, 0))
+        .collect();
+
+    for (date,) in dates {
+        let Ok(date) = DateTime::<Local>::from_str(&date) else {
+            continue;
+        };
+
+        if let Some((_, count)) = counts.iter_mut().find(|(x, _)| *x == date.date_naive()) {
+            *count += 1;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Marks a database row as deleted via its id, or removes it for good if `purge` is set.
+///
+/// Returns whether an entry with the given id was found. Entries already marked as
+/// deleted can still be purged.
+pub async fn delete_by_id(id: i64, purge: bool, pool: &Pool<Sqlite>) -> Result<bool, sqlx::Error> {
+    Ok(!delete_by_ids(&[id], purge, pool).await?.is_empty())
+}
+
+/// Marks database rows as deleted via their ids, or removes them for good if `purge` is set.
+///
+/// Returns the ids of the entries that were found, in ascending order. Each deletion
+/// is logged on its own, so that they are undone one at a time.
+pub async fn delete_by_ids(
+    ids: &[i64],
+    purge: bool,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let entries: Vec<TodoEntry> = select_by_ids(ids, purge)
+        .build_query_as()
+        .fetch_all(&mut transaction)
+        .await?;
+
+    if entries.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut query = if purge {
+        QueryBuilder::new("DELETE FROM todos WHERE 1=1")
+    } else {
+        QueryBuilder::new("UPDATE todos SET is_deleted = 1 WHERE 1=1")
+    };
+    push_ids(&mut query, ids);
+
+    query.build().execute(&mut transaction).await?;
+
+    let operation = if purge {
+        Operation::Purge
+    } else {
+        Operation::Delete
+    };
+
+    for entry in &entries {
+        log_operation(&mut transaction, operation.clone(), entry.id, Some(entry)).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(entries.iter().map(|x| x.id).collect())
+}
+
+/// Gets the entries that deleting the given ids would affect, in ascending order of id.
+pub async fn preview_delete_by_ids(
+    ids: &[i64],
+    purge: bool,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<Todo>, sqlx::Error> {
+    fetch_todos(select_by_ids(ids, purge), pool).await
+}
+
+/// Builds a query selecting the rows that deleting the given ids would affect.
+///
+/// Purging also affects rows that are already marked as deleted.
+fn select_by_ids(ids: &[i64], purge: bool) -> QueryBuilder<'_, Sqlite> {
+    let mut query = if purge {
+        QueryBuilder::new("SELECT * FROM todos WHERE 1=1")
+    } else {
+        QueryBuilder::new("SELECT * FROM todos WHERE is_deleted = 0")
+    };
+
+    push_ids(&mut query, ids);
+    query.push(" ORDER BY id");
+
+    query
+}
+
+/// Pushes a condition restricting the query to the given ids.
+///
+/// Expects the query to already contain a `WHERE` clause.
+fn push_ids(query: &mut QueryBuilder<Sqlite>, ids: &[i64]) {
+    query.push(" AND id IN (");
+    let mut separated = query.separated(", ");
+    for id in ids {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(")");
+}
+
+/// Marks all database rows matching the filters as deleted, or removes them for good
+/// if `purge` is set, returning how many were affected.
+///
+/// Each deletion is logged on its own, as in `delete_by_ids`.
+pub async fn delete_entries(
+    filters: &Filters,
+    purge: bool,
+    pool: &Pool<Sqlite>,
+) -> Result<u64, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let mut select = if purge {
+        QueryBuilder::new("SELECT * FROM todos WHERE 1=1")
+    } else {
+        QueryBuilder::new("SELECT * FROM todos WHERE is_deleted = 0")
+    };
+    push_filters(&mut select, filters);
+
+    let entries: Vec<TodoEntry> = select.build_query_as().fetch_all(&mut transaction).await?;
+
+    // The rows are deleted through the same filters rather than their ids, so that
+    // the number of bound parameters does not grow with the number of entries.
+    let mut query = if purge {
+        QueryBuilder::new("DELETE FROM todos WHERE 1=1")
+    } else {
+        QueryBuilder::new("UPDATE todos SET is_deleted = 1 WHERE is_deleted = 0")
+    };
+    push_filters(&mut query, filters);
+
+    let result = query.build().execute(&mut transaction).await?;
+
+    let operation = if purge {
+        Operation::Purge
+    } else {
+        Operation::Delete
+    };
+
+    for entry in &entries {
+        log_operation(&mut transaction, operation.clone(), entry.id, Some(entry)).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Gets the entries that deleting all rows matching the filters would affect.
+pub async fn preview_delete_entries(
+    filters: &Filters,
+    purge: bool,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<Todo>, sqlx::Error> {
+    let mut query = if purge {
+        QueryBuilder::new("SELECT * FROM todos WHERE 1=1")
+    } else {
+        QueryBuilder::new("SELECT * FROM todos WHERE is_deleted = 0")
+    };
+
+    push_filters(&mut query, filters);
+    query.push(" ORDER BY julianday(date) DESC");
+
+    fetch_todos(query, pool).await
+}
+
+/// Brings back a database row marked as deleted via its id.
+///
+/// Returns whether a deleted entry with the given id was found.
+pub async fn restore_by_id(id: i64, pool: &Pool<Sqlite>) -> Result<bool, sqlx::Error> {
+    let updated_at = to_stored(Local::now());
+    let q = query!(
+        "UPDATE todos SET is_deleted = 0, updated_at = ? WHERE id = ? AND is_deleted = 1",
+        updated_at,
+        id
+    );
+
+    let result = q.execute(pool).await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Outcome of marking an entry as done.
+pub enum Completion {
+    /// No entry with the given id was found.
+    NotFound,
+
+    /// The entry was moved to the completed table.
+    Done,
+
+    /// The entry was moved to the completed table, and its next occurrence was added.
+    Recurred { id: i64, due: DateTime<Local> },
+}
+
+/// Moves a database row to the completed table via its id.
+///
+/// If the entry is recurring, a fresh copy of it is added, dated now and due
+/// on the next occurrence given by its recurrence rule, with all subtasks unchecked.
+///
+/// Everything happens in a single transaction, so the entry can never be
+/// lost halfway through.
+pub async fn complete_by_id(id: i64, pool: &Pool<Sqlite>) -> Result<Completion, sqlx::Error> {
+    let now = DateTime::<Local>::from(time::SystemTime::now());
+    let completed_at = to_stored(now);
+
+    let mut transaction = pool.begin().await?;
+
+    let entry: Option<TodoEntry> =
+        sqlx::query_as("SELECT * FROM todos WHERE id = ? AND is_deleted = 0")
+            .bind(id)
+            .fetch_optional(&mut transaction)
+            .await?;
+
+    let Some(entry) = entry else {
+        return Ok(Completion::NotFound);
+    };
+
+    let q = query!(
+        "INSERT INTO completed_todos
+            (date, text, priority, due, tags, recurrence, subtasks, note, completed_at)
+        SELECT date, text, priority, due, tags, recurrence, subtasks, note, ? FROM todos WHERE id = ?",
+        completed_at,
+        id
+    );
+
+    q.execute(&mut transaction).await?;
+
+    let q = query!("DELETE FROM todos WHERE id = ?", id);
+
+    q.execute(&mut transaction).await?;
+
+    forget_operations(&mut transaction, id).await?;
+
+    let todo = Todo::from_entry(&entry)?;
+
+    let next_due = todo
+        .recurrence
+        .as_ref()
+        .and_then(|x| x.next_due(todo.due, now));
+
+    let completion = match next_due {
+        Some(due) => {
+            let due_to_store = to_stored(due);
+            let subtasks: Vec<Subtask> = todo
+                .subtasks
+                .into_iter()
+                .map(|x| Subtask { done: false, ..x })
+                .collect();
+            let subtasks = to_json(&subtasks);
+
+            let q = query!(
+                "INSERT INTO todos
+                    (date, text, priority, due, tags, recurrence, subtasks, updated_at, pinned, note)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                completed_at,
+                entry.text,
+                entry.priority,
+                due_to_store,
+                entry.tags,
+                entry.recurrence,
+                subtasks,
+                completed_at,
+                entry.pinned,
+                entry.note
+            );
+
+            let id = q.execute(&mut transaction).await?.last_insert_rowid();
+
+            forget_operations(&mut transaction, id).await?;
+
+            Completion::Recurred { id, due }
+        }
+        None => Completion::Done,
+    };
+
+    transaction.commit().await?;
+
+    Ok(completion)
+}
+
+/// Moves a database row to another database via its id, returning its new id if it was found.
+///
+/// The row is only deleted from the source once it is stored in the destination,
+/// so that a failure midway cannot lose the entry. Both databases must be different,
+/// see `database_location`.
+pub async fn move_by_id(
+    id: i64,
+    source: &Pool<Sqlite>,
+    destination: &Pool<Sqlite>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut transaction = source.begin().await?;
+
+    let entry: Option<TodoEntry> =
+        sqlx::query_as("SELECT * FROM todos WHERE id = ? AND is_deleted = 0")
+            .bind(id)
+            .fetch_optional(&mut transaction)
+            .await?;
+
+    let Some(entry) = entry else {
+        return Ok(None);
+    };
+
+    let q = query!("DELETE FROM todos WHERE id = ?", id);
+
+    q.execute(&mut transaction).await?;
+
+    // Each list logs its own side of the move, so that undoing on the destination removes
+    // the copy and undoing on the source brings the entry back.
+    log_operation(&mut transaction, Operation::Purge, id, Some(&entry)).await?;
+
+    let mut destination = destination.begin().await?;
+
+    let q = query!(
+        "INSERT INTO todos (
+            date, text, priority, due, tags, recurrence, subtasks, updated_at, pinned, note
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        entry.date,
+        entry.text,
+        entry.priority,
+        entry.due,
+        entry.tags,
+        entry.recurrence,
+        entry.subtasks,
+        entry.updated_at,
+        entry.pinned,
+        entry.note
+    );
+
+    let new_id = q.execute(&mut destination).await?.last_insert_rowid();
+
+    log_operation(&mut destination, Operation::Add, new_id, None).await?;
+
+    destination.commit().await?;
+    transaction.commit().await?;
+
+    Ok(Some(new_id))
+}
+
+/// Adds a copy of a database row via its id, dated now and optionally with another text.
+///
+/// Priority, due date, tags, recurrence, subtasks, blocker and note are copied, with subtasks
+/// unchecked.
+/// Returns the id of the copy if an entry with the given id was found.
+pub async fn copy_by_id(
+    id: i64,
+    text: Option<&str>,
+    pool: &Pool<Sqlite>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let entry = fetch_entry(&mut transaction, id).await?;
+    let Some(entry) = entry.filter(|x| x.is_deleted == 0) else {
+        return Ok(None);
+    };
+
+    let to_store = to_stored(Local::now());
+    let text = text.unwrap_or(&entry.text);
+    let subtasks: Vec<Subtask> = entry
+        .subtasks()?
+        .into_iter()
+        .map(|x| Subtask { done: false, ..x })
+        .collect();
+    let subtasks = to_json(&subtasks);
+
+    let q = query!(
+        "INSERT INTO todos (
+            date, text, priority, due, tags, recurrence, subtasks, updated_at, blocked_by, note
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        to_store,
+        text,
+        entry.priority,
+        entry.due,
+        entry.tags,
+        entry.recurrence,
+        subtasks,
+        to_store,
+        entry.blocked_by,
+        entry.note
+    );
+
+    let new_id = q.execute(&mut transaction).await?.last_insert_rowid();
+
+    log_operation(&mut transaction, Operation::Add, new_id, None).await?;
+
+    transaction.commit().await?;
+
+    Ok(Some(new_id))
+}
+
+/// Updates text, priority, blocker and/or note of a database row via its id.
+///
+/// The blocker is unset if given as `Some(None)`, and is expected to be checked beforehand
+/// through `check_blocker` otherwise. The note is unset if given empty. The date is kept untouched, so that editing does not
+/// reorder the list. Returns whether an entry with the given id was found.
+pub async fn edit_by_id(
+    id: i64,
+    text: Option<&str>,
+    priority: Option<Priority>,
+    blocked_by: Option<Option<i64>>,
+    note: Option<&str>,
+    pool: &Pool<Sqlite>,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let entry = fetch_entry(&mut transaction, id).await?;
+    let Some(entry) = entry.filter(|x| x.is_deleted == 0) else {
+        return Ok(false);
+    };
+
+    let mut query = QueryBuilder::new("UPDATE todos SET ");
+    let mut fields = query.separated(", ");
+
+    if let Some(x) = text {
+        fields.push("text = ");
+        fields.push_bind_unseparated(x);
+    }
+
+    if let Some(x) = priority {
+        fields.push("priority = ");
+        fields.push_bind_unseparated(x as i64);
+    }
+
+    if let Some(x) = blocked_by {
+        fields.push("blocked_by = ");
+        fields.push_bind_unseparated(x);
+    }
+
+    if let Some(x) = note {
+        fields.push("note = ");
+        fields.push_bind_unseparated(Some(x).filter(|x| !x.is_empty()));
+    }
+
+    fields.push("updated_at = ");
+    fields.push_bind_unseparated(to_stored(Local::now()));
+
+    query.push(" WHERE id = ");
+    query.push_bind(id);
+
+    query.build().execute(&mut transaction).await?;
+
+    log_operation(&mut transaction, Operation::Edit, id, Some(&entry)).await?;
+
+    transaction.commit().await?;
+
+    Ok(true)
+}
+
+/// Checks that an entry can be blocked by another one, which must be on the list.
+///
+/// If the blocked entry is already on the list, its blocker cannot be blocked by it, directly
+/// or through other entries, since neither could ever be done first.
+pub async fn check_blocker(
+    id: Option<i64>,
+    blocker: i64,
+    pool: &Pool<Sqlite>,
+) -> Result<(), AppError> {
+    let found: Option<(i64,)> =
+        sqlx::query_as("SELECT id FROM todos WHERE id = ? AND is_deleted = 0")
+            .bind(blocker)
+            .fetch_optional(pool)
+            .await?;
+
+    if found.is_none() {
+        return Err(AppError::BlockerNotFound(blocker));
+    }
+
+    let mut current = Some(blocker);
+    while let Some(x) = current {
+        if Some(x) == id {
+            return Err(AppError::CyclicBlocker(blocker));
+        }
+
+        let next: Option<(Option<i64>,)> =
+            sqlx::query_as("SELECT blocked_by FROM todos WHERE id = ?")
+                .bind(x)
+                .fetch_optional(pool)
+                .await?;
+
+        current = next.and_then(|(x,)| x);
+    }
+
+    Ok(())
+}
+
+/// Change of the text of an entry, as given by a replacement across entries.
+pub struct Replacement {
+    pub id: i64,
+    pub old: String,
+    pub new: String,
+}
+
+/// Gets the changes that replacing `from` by `to` in the text of the entries matching the
+/// filters would make, most recent entries first. Entries whose text is unchanged are left out.
+///
+/// New texts are trimmed, as those of new entries, and entries whose text would become
+/// blank are left out with a warning.
+pub async fn find_replacements(
+    filters: &Filters,
+    from: &str,
+    to: &str,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<Replacement>, sqlx::Error> {
+    Ok(
+        get_entries(filters, false, &SortField::Date, &[], None, pool)
+            .await?
+            .into_iter()
+            .filter(|x| x.text.contains(from))
+            .filter_map(|x| {
+                let Ok(new) = to_text(&x.text.replace(from, to)) else {
+                    eprintln!(
+                        "Warning: skipping entry #{}, whose text would be empty.",
+                        x.id
+                    );
+                    return None;
+                };
+
+                (new != x.text).then(|| Replacement {
+                    id: x.id,
+                    new,
+                    old: x.text,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Writes the new texts of the replacements in a single transaction, returning how many were written.
+///
+/// Entries whose text changed since the replacements were found are skipped. Each edit
+/// is logged on its own, so that they are undone one at a time.
+pub async fn apply_replacements(
+    replacements: &[Replacement],
+    pool: &Pool<Sqlite>,
+) -> Result<u64, sqlx::Error> {
+    let updated_at = to_stored(Local::now());
+    let mut count = 0;
+
+    let mut transaction = pool.begin().await?;
+
+    for replacement in replacements {
+        let entry = fetch_entry(&mut transaction, replacement.id).await?;
+        let Some(entry) = entry.filter(|x| x.is_deleted == 0 && x.text == replacement.old) else {
+            continue;
+        };
+
+        let q = query!(
+            "UPDATE todos SET text = ?, updated_at = ? WHERE id = ?",
+            replacement.new,
+            updated_at,
+            replacement.id
+        );
+
+        q.execute(&mut transaction).await?;
+
+        log_operation(&mut transaction, Operation::Edit, entry.id, Some(&entry)).await?;
+
+        count += 1;
+    }
+
+    transaction.commit().await?;
+
+    Ok(count)
+}
+
+/// Sets the priority of every entry with the given priority to another one in a single
+/// transaction, returning how many were changed.
+///
+/// Each change is logged on its own, so that they are undone one at a time.
+pub async fn reprioritize(
+    from: Priority,
+    to: Priority,
+    pool: &Pool<Sqlite>,
+) -> Result<u64, sqlx::Error> {
+    let (from, to) = (from.to_i64(), to.to_i64());
+    if from == to {
+        return Ok(0);
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    let entries: Vec<TodoEntry> =
+        sqlx::query_as("SELECT * FROM todos WHERE priority = ? AND is_deleted = 0")
+            .bind(from)
+            .fetch_all(&mut transaction)
+            .await?;
+
+    let updated_at = to_stored(Local::now());
+    let q = query!(
+        "UPDATE todos SET priority = ?, updated_at = ? WHERE priority = ? AND is_deleted = 0",
+        to,
+        updated_at,
+        from
+    );
+
+    q.execute(&mut transaction).await?;
+
+    for entry in &entries {
+        log_operation(&mut transaction, Operation::Edit, entry.id, Some(entry)).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(entries.len() as u64)
+}
+
+/// Raises or lowers the priority of a database row by one level via its id.
+///
+/// Returns the old and new priorities. Fails if no entry has the given id, or if its priority
+/// is already at the highest or lowest level, in which case nothing is updated.
+pub async fn bump_by_id(
+    id: i64,
+    down: bool,
+    pool: &Pool<Sqlite>,
+) -> Result<(Priority, Priority), AppError> {
+    let mut transaction = pool.begin().await?;
+
+    let entry = fetch_entry(&mut transaction, id).await?;
+    let Some(entry) = entry.filter(|x| x.is_deleted == 0) else {
+        return Err(AppError::EntryNotFound(id));
+    };
+
+    let old = entry.priority();
+    let step = if down { -1 } else { 1 };
+
+    let Some(new) = Priority::from_i64(old.to_i64() + step) else {
+        return Err(AppError::PriorityAtLimit(id, old));
+    };
+
+    let new_priority = new.to_i64();
+    let updated_at = to_stored(Local::now());
+    let q = query!(
+        "UPDATE todos SET priority = ?, updated_at = ? WHERE id = ?",
+        new_priority,
+        updated_at,
+        id
+    );
+
+    q.execute(&mut transaction).await?;
+
+    log_operation(&mut transaction, Operation::Edit, id, Some(&entry)).await?;
+
+    transaction.commit().await?;
+
+    Ok((old, new))
+}
+
+/// Pins or unpins a database row via its id, so that it is listed before the other entries.
+///
+/// Returns whether an entry with the given id was found.
+pub async fn pin_by_id(id: i64, pinned: bool, pool: &Pool<Sqlite>) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let entry = fetch_entry(&mut transaction, id).await?;
+    let Some(entry) = entry.filter(|x| x.is_deleted == 0) else {
+        return Ok(false);
+    };
+
+    let pinned = i64::from(pinned);
+    let updated_at = to_stored(Local::now());
+    let q = query!(
+        "UPDATE todos SET pinned = ?, updated_at = ? WHERE id = ?",
+        pinned,
+        updated_at,
+        id
+    );
+
+    q.execute(&mut transaction).await?;
+
+    log_operation(&mut transaction, Operation::Edit, id, Some(&entry)).await?;
+
+    transaction.commit().await?;
+
+    Ok(true)
+}
+
+/// Toggles whether a subtask of a database row is done, via the id of the row and the
+/// position of the subtask, starting at 1.
+///
+/// Returns the subtask as it is after toggling, if it was found.
+pub async fn check_subtask(
+    id: i64,
+    index: usize,
+    pool: &Pool<Sqlite>,
+) -> Result<Option<Subtask>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let entry = fetch_entry(&mut transaction, id).await?;
+    let Some(entry) = entry.filter(|x| x.is_deleted == 0) else {
+        return Ok(None);
+    };
+
+    let mut todo = Todo::from_entry(&entry)?;
+    let Some(subtask) = index.checked_sub(1).and_then(|x| todo.subtasks.get_mut(x)) else {
+        return Ok(None);
+    };
+
+    subtask.done = !subtask.done;
+    let subtask = subtask.clone();
+
+    let subtasks = to_json(&todo.subtasks);
+    let updated_at = to_stored(Local::now());
+    let q = query!(
+        "UPDATE todos SET subtasks = ?, updated_at = ? WHERE id = ?",
+        subtasks,
+        updated_at,
+        id
+    );
+
+    q.execute(&mut transaction).await?;
+
+    log_operation(&mut transaction, Operation::Edit, id, Some(&entry)).await?;
+
+    transaction.commit().await?;
+
+    Ok(Some(subtask))
+}
+
+/// Serializes subtasks as they are stored in the database.
+fn to_json(subtasks: &[Subtask]) -> String {
+    serde_json::to_string(subtasks).expect("Subtasks should always be serializable.")
+}
+
+/// Operation on an entry that can be undone.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    Add,
+    Delete,
+    Purge,
+    Edit,
+}
+
+impl Operation {
+    fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "add" => Some(Operation::Add),
+            "delete" => Some(Operation::Delete),
+            "purge" => Some(Operation::Purge),
+            "edit" => Some(Operation::Edit),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Add => "add",
+            Operation::Delete => "delete",
+            Operation::Purge => "purge",
+            Operation::Edit => "edit",
+        }
+    }
+}
+
+/// Fetches a whole database row via its id, be it marked as deleted or not.
+async fn fetch_entry(
+    transaction: &mut Transaction<'_, Sqlite>,
+    id: i64,
+) -> Result<Option<TodoEntry>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM todos WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *transaction)
+        .await
+}
+
+/// Records an operation on the log, alongside the entry as it was before it, so that it can be undone.
+async fn log_operation(
+    transaction: &mut Transaction<'_, Sqlite>,
+    operation: Operation,
+    id: i64,
+    snapshot: Option<&TodoEntry>,
+) -> Result<(), sqlx::Error> {
+    let operation = operation.name();
+    let snapshot = snapshot
+        .map(serde_json::to_string)
+        .transpose()
+        .expect("TodoEntries should always be serializable.");
+    let created_at = to_stored(DateTime::<Local>::from(time::SystemTime::now()));
+
+    let q = query!(
+        "INSERT INTO operations_log (operation, todo_id, snapshot, created_at) VALUES (?, ?, ?, ?)",
+        operation,
+        id,
+        snapshot,
+        created_at
+    );
+
+    q.execute(&mut *transaction).await?;
+
+    Ok(())
+}
+
+/// Removes the logged operations of an entry that leaves the table, or that enters it without
+/// being logged, so that undoing cannot act on another entry that reuses its id.
+async fn forget_operations(
+    transaction: &mut Transaction<'_, Sqlite>,
+    id: i64,
+) -> Result<(), sqlx::Error> {
+    let q = query!("DELETE FROM operations_log WHERE todo_id = ?", id);
+
+    q.execute(&mut *transaction).await?;
+
+    Ok(())
+}
+
+/// Reverts the most recent add, delete, edit, bump, check, pin or move, removing it from the log.
+///
+/// Returns the operation undone and the id of the entry it affected, or `None` if
+/// there was nothing to undo. A purged entry is restored with its original id, unless
+/// that id was taken in the meantime, in which case it gets a new one. Operations on entries
+/// that were since done, archived or quarantined are forgotten, see `forget_operations`.
+pub async fn undo(pool: &Pool<Sqlite>) -> Result<Option<(Operation, i64)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let logged: Option<(i64, String, i64, Option<String>)> = sqlx::query_as(
+        "SELECT id, operation, todo_id, snapshot FROM operations_log ORDER BY id DESC LIMIT 1",
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+
+    let Some((log_id, operation, mut id, snapshot)) = logged else {
+        return Ok(None);
+    };
+
+    let operation = Operation::from_name(&operation).expect("Logged operations should be valid.");
+    let snapshot: Option<TodoEntry> = snapshot
+        .map(|x| serde_json::from_str(&x))
+        .transpose()
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+    // The blocker of a snapshot may have been done or purged since, so it is only restored
+    // if it is still on the table.
+    match (&operation, snapshot) {
+        (Operation::Add, _) => {
+            query!("DELETE FROM todos WHERE id = ?", id)
+                .execute(&mut transaction)
+                .await?;
+        }
+        (Operation::Delete, _) => {
+            query!("UPDATE todos SET is_deleted = 0 WHERE id = ?", id)
+                .execute(&mut transaction)
+                .await?;
+        }
+        (Operation::Purge, Some(entry)) => {
+            let taken = fetch_entry(&mut transaction, id).await?.is_some();
+            let restored_id = if taken { None } else { Some(id) };
+
+            let q = query!(
+                "INSERT INTO todos (
+                    id, date, text, priority, due, tags, recurrence, is_deleted, subtasks,
+                    updated_at, pinned, blocked_by, note
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, (SELECT id FROM todos WHERE id = ?), ?)",
+                restored_id,
+                entry.date,
+                entry.text,
+                entry.priority,
+                entry.due,
+                entry.tags,
+                entry.recurrence,
+                entry.is_deleted,
+                entry.subtasks,
+                entry.updated_at,
+                entry.pinned,
+                entry.blocked_by,
+                entry.note
+            );
+
+            id = q.execute(&mut transaction).await?.last_insert_rowid();
+        }
+        (Operation::Edit, Some(entry)) => {
+            query!(
+                "UPDATE todos
+                SET text = ?, priority = ?, subtasks = ?, updated_at = ?, pinned = ?,
+                    blocked_by = (SELECT id FROM todos WHERE id = ?), note = ?
+                WHERE id = ?",
+                entry.text,
+                entry.priority,
+                entry.subtasks,
+                entry.updated_at,
+                entry.pinned,
+                entry.blocked_by,
+                entry.note,
+                id
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        (Operation::Purge | Operation::Edit, None) => {
+            panic!("Logged purges and edits should always have a snapshot.")
+        }
+    }
+
+    query!("DELETE FROM operations_log WHERE id = ?", log_id)
+        .execute(&mut transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(Some((operation, id)))
+}
+
+/// Moves all entries older than the given datetime to the archived table, returning how many were moved.
+///
+/// The archived table is only created once something is archived. Insertion and
+/// deletion happen in a single transaction, so that no entry can be lost halfway through.
+pub async fn archive_before(
+    before: DateTime<Local>,
+    pool: &Pool<Sqlite>,
+) -> Result<u64, sqlx::Error> {
+    let before = to_stored(before);
+    let archived_at = to_stored(DateTime::<Local>::from(time::SystemTime::now()));
+
+    let mut transaction = pool.begin().await?;
+
+    let q = query!(
+        "CREATE TABLE IF NOT EXISTS archived_todos (
+            id INTEGER PRIMARY KEY,
+            date TEXT NOT NULL,
+            text TEXT NOT NULL,
+            priority INTEGER NOT NULL,
+            due TEXT,
+            tags TEXT NOT NULL DEFAULT '',
+            recurrence TEXT,
+            is_deleted INTEGER NOT NULL DEFAULT 0,
+            subtasks TEXT NOT NULL DEFAULT '[]',
+            archived_at TEXT NOT NULL,
+            note TEXT
+        ) STRICT"
+    );
+
+    q.execute(&mut transaction).await?;
+
+    let q = query!(
+        "INSERT INTO archived_todos
+            (date, text, priority, due, tags, recurrence, is_deleted, subtasks, archived_at, note)
+        SELECT date, text, priority, due, tags, recurrence, is_deleted, subtasks, ?, note
+        FROM todos WHERE julianday(date) < julianday(?)",
+        archived_at,
+        before
+    );
+
+    let archived = q.execute(&mut transaction).await?.rows_affected();
+
+    let q = query!(
+        "DELETE FROM operations_log
+        WHERE todo_id IN (SELECT id FROM todos WHERE julianday(date) < julianday(?))",
+        before
+    );
+
+    q.execute(&mut transaction).await?;
+
+    let q = query!(
+        "DELETE FROM todos WHERE julianday(date) < julianday(?)",
+        before
+    );
+
+    q.execute(&mut transaction).await?;
+
+    transaction.commit().await?;
+
+    Ok(archived)
+}
+
+/// Gets the entries that archiving everything older than the given datetime would move.
+pub async fn preview_archive_before(
+    before: DateTime<Local>,
+    pool: &Pool<Sqlite>,
+) -> Result<Vec<Todo>, sqlx::Error> {
+    let mut query = QueryBuilder::new("SELECT * FROM todos WHERE julianday(date) < julianday(");
+    query.push_bind(to_stored(before));
+    query.push(") ORDER BY julianday(date) DESC");
+
+    fetch_todos(query, pool).await
+}
+
+/// Outcome of checking the integrity of a TODO list.
+pub struct Diagnosis {
+    /// Number of rows checked, including those marked as deleted.
+    pub checked: usize,
+
+    /// Ids of the malformed rows, alongside a description of what is wrong with them.
+    pub problems: Vec<(i64, String)>,
+}
+
+/// Describes what is wrong with a row that cannot be read as a TODO, if anything.
+fn find_problems(entry: &TodoEntry) -> Option<String> {
+    let mut problems = Vec::new();
+
+    if Priority::from_i64(entry.priority).is_none() {
+        problems.push(format!("invalid priority {}", entry.priority));
+    }
+
+    let dates = [
+        ("date", Some(&entry.date)),
+        ("due date", entry.due.as_ref()),
+        ("update date", entry.updated_at.as_ref()),
+    ];
+    for (name, date) in dates {
+        if let Some(x) = date.filter(|x| DateTime::<Local>::from_str(x).is_err()) {
+            problems.push(format!("invalid {} {:?}", name, x));
+        }
+    }
+
+    if entry.subtasks().is_err() {
+        problems.push(format!("invalid subtasks {:?}", entry.subtasks));
+    }
+
+    (!problems.is_empty()).then(|| problems.join(", "))
+}
+
+/// Checks that every row of the database can be read as a TODO.
+///
+/// If `fix` is set, malformed rows are moved to the `malformed_todos` table, created
+/// only once something is moved, alongside the description of their problems.
+pub async fn diagnose(fix: bool, pool: &Pool<Sqlite>) -> Result<Diagnosis, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let entries: Vec<TodoEntry> = sqlx::query_as("SELECT * FROM todos ORDER BY id")
+        .fetch_all(&mut transaction)
+        .await?;
+
+    let problems: Vec<(i64, String)> = entries
+        .iter()
+        .filter_map(|x| find_problems(x).map(|problem| (x.id, problem)))
+        .collect();
+
+    if fix && !problems.is_empty() {
+        let quarantined_at = to_stored(DateTime::<Local>::from(time::SystemTime::now()));
+
+        let q = query!(
+            "CREATE TABLE IF NOT EXISTS malformed_todos (
+                id INTEGER PRIMARY KEY,
+                todo_id INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                text TEXT NOT NULL,
+                priority INTEGER NOT NULL,
+                due TEXT,
+                tags TEXT NOT NULL DEFAULT '',
+                recurrence TEXT,
+                is_deleted INTEGER NOT NULL DEFAULT 0,
+                subtasks TEXT NOT NULL DEFAULT '[]',
+                updated_at TEXT,
+                pinned INTEGER NOT NULL DEFAULT 0,
+                problem TEXT NOT NULL,
+                quarantined_at TEXT NOT NULL,
+                note TEXT
+            ) STRICT"
+        );
+
+        q.execute(&mut transaction).await?;
+
+        for (id, problem) in &problems {
+            let q = query!(
+                "INSERT INTO malformed_todos (
+                    todo_id, date, text, priority, due, tags, recurrence, is_deleted, subtasks,
+                    updated_at, pinned, problem, quarantined_at, note
+                )
+                SELECT id, date, text, priority, due, tags, recurrence, is_deleted, subtasks,
+                    updated_at, pinned, ?, ?, note
+                FROM todos WHERE id = ?",
+                problem,
+                quarantined_at,
+                id
+            );
+
+            q.execute(&mut transaction).await?;
+
+            let q = query!("DELETE FROM todos WHERE id = ?", id);
+
+            q.execute(&mut transaction).await?;
+
+            forget_operations(&mut transaction, *id).await?;
+        }
+    }
+
+    transaction.commit().await?;
+
+    Ok(Diagnosis {
+        checked: entries.len(),
+        problems,
+    })
+}
+
+/// Deletes all entries of database, also resetting the ids so that the next entry added is #1,
+/// and returns how many were deleted.
+///
+/// Ids are only reused by sqlite once no row has a higher one, so emptying the table is enough
+/// on its own, but the sequence is also reset in case the table was created with `AUTOINCREMENT`.
+/// The operations log is cleared as well, since undoing would otherwise act on reused ids.
+pub async fn prune(pool: &Pool<Sqlite>) -> Result<u64, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let q = query!("DELETE FROM todos");
+
+    let count = q.execute(&mut transaction).await?.rows_affected();
+
+    sqlx::query("DELETE FROM operations_log")
+        .execute(&mut transaction)
+        .await?;
+
+    let (has_sequence,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'sqlite_sequence')",
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+
+    if has_sequence {
+        sqlx::query("DELETE FROM sqlite_sequence WHERE name = 'todos'")
+            .execute(&mut transaction)
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(count)
+}
+
+/// Gets the entries that pruning would delete, i.e. all of them, including those marked as deleted.
+pub async fn preview_prune(pool: &Pool<Sqlite>) -> Result<Vec<Todo>, sqlx::Error> {
+    let query = QueryBuilder::new("SELECT * FROM todos ORDER BY julianday(date) DESC");
+
+    fetch_todos(query, pool).await
+}
+
+/// Creates the tables if needed and brings their schema up to date.
+pub async fn setup_database(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        "CREATE TABLE IF NOT EXISTS todos (
+            id INTEGER PRIMARY KEY,
+            date TEXT NOT NULL,
+            text TEXT NOT NULL,
+            priority INTEGER NOT NULL
+        ) STRICT"
+    );
+    query.execute(pool).await?;
+
+    let query = sqlx::query!(
+        "CREATE TABLE IF NOT EXISTS completed_todos (
+            id INTEGER PRIMARY KEY,
+            date TEXT NOT NULL,
+            text TEXT NOT NULL,
+            priority INTEGER NOT NULL,
+            completed_at TEXT NOT NULL
+        ) STRICT"
+    );
+    query.execute(pool).await?;
+
+    migrate(pool).await
+}
+
+/// Applies the schema migrations that have not been applied to the database yet.
+async fn migrate(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let (version,): (i64,) = sqlx::query_as("PRAGMA user_version")
+        .fetch_one(pool)
+        .await?;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let mut transaction = pool.begin().await?;
+
+        sqlx::query(migration).execute(&mut transaction).await?;
+        sqlx::query(&format!("PRAGMA user_version = {}", i + 1))
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the folder of the global todo list.
+///
+/// On Linux, it is the `cltodo` folder of the data directory, i.e. `$XDG_DATA_HOME` or else
+/// `~/.local/share`. A global list still under `~/.cltodo` is moved there the first time,
+/// and kept in place with a warning if it cannot be moved. Elsewhere, it is `~/.cltodo`.
+fn global_folder() -> Result<PathBuf, AppError> {
+    let legacy = home_dir().ok_or(AppError::HomeDirNotFound)?.join(DB_FOLDER);
+
+    let folder = match dirs::data_dir() {
+        Some(x) if cfg!(target_os = "linux") => x.join(DATA_FOLDER),
+        _ => return Ok(legacy),
+    };
+
+    let to_move = [DB_FILE, LISTS_FOLDER]
+        .into_iter()
+        .filter(|x| legacy.join(x).exists() && !folder.join(x).exists());
+
+    for name in to_move {
+        info!(
+            "Moving {} to {}.",
+            legacy.join(name).display(),
+            folder.display()
+        );
+
+        let moved =
+            create_dir_all(&folder).and_then(|_| fs::rename(legacy.join(name), folder.join(name)));
+
+        if let Err(e) = moved {
+            eprintln!(
+                "Warning: could not move {} to {}, keeping the global list in place: {}.",
+                legacy.join(name).display(),
+                folder.display(),
+                e
+            );
+            return Ok(legacy);
+        }
+    }
+
+    Ok(folder)
+}
+
+/// Where the database of a todo list is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatabaseLocation {
+    /// Url given through the `CLTODO_DB_URL` environment variable.
+    Url(String),
+
+    /// Path of the database file.
+    File(PathBuf),
+}
+
+impl DatabaseLocation {
+    /// Whether both locations point to the same database, even through different paths.
+    pub fn is_same(&self, other: &DatabaseLocation) -> bool {
+        match (self, other) {
+            (DatabaseLocation::File(a), DatabaseLocation::File(b)) => {
+                let canonical = |x: &PathBuf| x.canonicalize().unwrap_or_else(|_| x.clone());
+                canonical(a) == canonical(b)
+            }
+            _ => self == other,
+        }
+    }
+}
+
+/// Returns a pool of connections to the sqlite database.
+///
+/// The database is found as described in `database_location`.
+pub async fn get_connection(
+    global: bool,
+    project_root: Option<&Path>,
+    list: Option<&str>,
+) -> Result<Pool<Sqlite>, AppError> {
+    open_database(&database_location(global, project_root, list)?).await
+}
+
+/// Returns a pool of connections to the database at the given location, setting it up if needed.
+pub async fn open_database(location: &DatabaseLocation) -> Result<Pool<Sqlite>, AppError> {
+    match location {
+        DatabaseLocation::Url(url) => get_connection_from_url(url).await,
+        DatabaseLocation::File(data_file) => {
+            if let Some(folder) = data_file.parent() {
+                create_dir_all(folder)?;
+            }
+
+            info!("Using the database at {}.", data_file.display());
+
+            connect(SqliteConnectOptions::new().filename(data_file), false).await
+        }
+    }
+}
+
+/// Finds the database of a todo list, without opening it.
+///
+/// Unless global, the `.cltodo` folder lives in the given project root, or else in the
+/// root of the current git repository, falling back to the global folder.
+///
+/// If a list name is given, its database lives under the `lists` subfolder of the
+/// resolved `.cltodo` folder. If the `CLTODO_DB_URL` environment variable is set,
+/// its database is used instead, regardless of the other parameters.
+pub fn database_location(
+    global: bool,
+    project_root: Option<&Path>,
+    list: Option<&str>,
+) -> Result<DatabaseLocation, AppError> {
+    if let Ok(url) = env::var(DB_URL_VAR) {
+        info!("Using the database at {} from {}.", url, DB_URL_VAR);
+        return Ok(DatabaseLocation::Url(url));
+    }
+
+    let cltodo_folder = if global {
+        global_folder()?
+    } else if let Some(root) = project_root {
+        info!("Using the given project root {}.", root.display());
+        root.join(DB_FOLDER)
+    } else if let Some(output) = Command::new("git")
+        .arg("rev-parse")
+        .arg("--show-toplevel")
+        .output()
+        .ok()
+        .filter(|x| x.status.success())
+    {
+        let stdout = std::str::from_utf8(&output.stdout).map_err(|_| {
+            AppError::InvalidPath(String::from_utf8_lossy(&output.stdout).trim().into())
+        })?;
+
+        info!("Found git repository at {}.", stdout.trim());
+        PathBuf::from(stdout.trim()).join(DB_FOLDER)
+    } else {
+        info!("Not inside a git repository, or git is not available.");
+        if !is_quiet() {
+            eprintln!("Note: not inside a git repository, using the global todo list.");
+        }
+        global_folder()?
+    };
+
+    let cltodo_folder = match list {
+        Some(x) => cltodo_folder.join(LISTS_FOLDER).join(x),
+        None => cltodo_folder,
+    };
+
+    Ok(DatabaseLocation::File(cltodo_folder.join(DB_FILE)))
+}
+
+/// Returns a pool of connections to the sqlite database at the given url, setting it up if needed.
+///
+/// In-memory databases, such as `sqlite::memory:`, are kept in a single connection
+/// that is never closed, since each connection would otherwise get its own database.
+pub async fn get_connection_from_url(url: &str) -> Result<Pool<Sqlite>, AppError> {
+    let in_memory = url.contains(":memory:") || url.contains("mode=memory");
+
+    connect(SqliteConnectOptions::from_str(url)?, in_memory).await
+}
+
+/// Returns a pool of connections to the sqlite database with the given options, setting it up if needed.
+///
+/// The database file is created if it does not exist yet.
+async fn connect(options: SqliteConnectOptions, in_memory: bool) -> Result<Pool<Sqlite>, AppError> {
+    let pool_options = if in_memory {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        SqlitePoolOptions::new().max_connections(5)
+    };
+
+    let options = options.create_if_missing(true);
+
+    // Concurrent runs, e.g. from several shells, wait for each other instead of failing with
+    // "database is locked", and readers are never blocked by a writer thanks to WAL.
+    let options = if in_memory {
+        options
+    } else {
+        options
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(time::Duration::from_secs(BUSY_TIMEOUT_SECS))
+    };
+
+    // SQLCipher needs the key before anything else reads the file, including the pragmas above,
+    // so this relies on sqlx running it first. The round-trip test checks that it does.
+    #[cfg(feature = "sqlcipher")]
+    let options = match DB_KEY.get() {
+        Some(key) => options.pragma("key", format!("'{}'", key.replace('\'', "''"))),
+        None => options,
+    };
+
+    // Connections opened before a migration may keep reading rows with the old columns,
+    // so a database file is set up through a connection of its own before the pool is opened.
+    if !in_memory {
+        let setup_pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options.clone())
+            .await?;
+
+        setup_database(&setup_pool).await?;
+        setup_pool.close().await;
+    }
+
+    let pool = pool_options.connect_with(options).await?;
+
+    if in_memory {
+        setup_database(&pool).await?;
+    }
+
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    async fn memory_pool() -> Pool<Sqlite> {
+        get_connection_from_url("sqlite::memory:").await.unwrap()
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(year, month, day, hour, 0, 0)
+            .unwrap()
+    }
+
+    async fn add_at(pool: &Pool<Sqlite>, text: &str, date: DateTime<Local>, priority: Priority) {
+        post_todos(
+            &[text.to_owned()],
+            pool,
+            Some(date),
+            priority,
+            None,
+            &[],
+            None,
+            &[],
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    fn texts(todos: &[Todo]) -> Vec<&str> {
+        todos.iter().map(|x| x.text.as_str()).collect()
+    }
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn short_dates_late_in_the_year_are_last_year_in_january() {
+        let today = ymd(2024, 1, 10);
+
+        assert_eq!(parse_short_date("12-25", today), Some(ymd(2023, 12, 25)));
+        assert_eq!(parse_short_date("25/12", today), Some(ymd(2023, 12, 25)));
+        assert_eq!(parse_short_date("01-05", today), Some(ymd(2024, 1, 5)));
+    }
+
+    #[test]
+    fn short_dates_up_to_six_months_ahead_are_this_year() {
+        let today = ymd(2024, 1, 10);
+
+        assert_eq!(parse_short_date("07-10", today), Some(ymd(2024, 7, 10)));
+        assert_eq!(parse_short_date("07-11", today), Some(ymd(2023, 7, 11)));
+    }
+
+    #[test]
+    fn short_dates_must_exist_in_the_resolved_year() {
+        assert_eq!(
+            parse_short_date("02-29", ymd(2024, 3, 1)),
+            Some(ymd(2024, 2, 29))
+        );
+        assert_eq!(parse_short_date("02-29", ymd(2023, 3, 1)), None);
+        assert_eq!(parse_short_date("13-01", ymd(2024, 3, 1)), None);
+    }
+
+    #[tokio::test]
+    async fn to_date_includes_the_whole_day() {
+        let pool = memory_pool().await;
+        add_at(&pool, "Evening", at(2023, 5, 10, 18), Priority::Normal).await;
+        add_at(&pool, "Next day", at(2023, 5, 11, 1), Priority::Normal).await;
+
+        let filters = Filters {
+            to: Some(to_datetime_to("2023-05-10").unwrap()),
+            ..Default::default()
+        };
+        let todos = get_entries(&filters, false, &SortField::Date, &[], None, &pool)
+            .await
+            .unwrap();
+
+        assert_eq!(texts(&todos), ["Evening"]);
+    }
+
+    #[test]
+    fn import_rejects_blank_texts() {
+        for text in ["", "   "] {
+            let content = format!(
+                r#"[{{"text": "Buy milk", "priority": "normal"}}, {{"text": "{}", "priority": "normal"}}]"#,
+                text
+            );
+
+            match parse_import(&content) {
+                Err(AppError::InvalidImport(e)) => assert!(e.contains("index 1"), "{}", e),
+                x => panic!("expected an invalid import, got {:?}", x),
+            }
+        }
+    }
+
+    #[test]
+    fn import_trims_texts_and_tags() {
+        let content = r#"[{"text": "  Buy milk ", "priority": "normal", "tags": [" home "]}]"#;
+
+        let todos = parse_import(content).unwrap();
+
+        assert_eq!(todos[0].text, "Buy milk");
+        assert_eq!(todos[0].tags, ["home"]);
+    }
+
+    #[test]
+    fn import_rejects_tags_with_commas() {
+        let content = r#"[{"text": "Buy milk", "priority": "normal", "tags": ["a,b"]}]"#;
+
+        assert!(matches!(
+            parse_import(content),
+            Err(AppError::InvalidImport(_))
+        ));
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[tokio::test]
+    async fn encrypted_list_round_trips() {
+        let path = env::temp_dir().join(format!("cltodo-key-{}.db", std::process::id()));
+        let url = format!("sqlite://{}", path.display());
+
+        set_db_key("correct horse".to_string());
+
+        let pool = get_connection_from_url(&url).await.unwrap();
+        post_todo("Secret", &pool, Priority::Normal, None, &[], None, &[])
+            .await
+            .unwrap();
+        pool.close().await;
+
+        let pool = get_connection_from_url(&url).await.unwrap();
+        let todos = get_entries(
+            &Filters::default(),
+            false,
+            &SortField::Date,
+            &[],
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+        pool.close().await;
+
+        let header = fs::read(&path).unwrap();
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].text, "Secret");
+        assert!(!header.starts_with(b"SQLite format 3"));
+    }
+
+    /// Adds normal and critical entries alternately, on consecutive days.
+    async fn interleaved_pool() -> Pool<Sqlite> {
+        let pool = memory_pool().await;
+        add_at(&pool, "A", at(2023, 5, 1, 12), Priority::Normal).await;
+        add_at(&pool, "B", at(2023, 5, 2, 12), Priority::Critical).await;
+        add_at(&pool, "C", at(2023, 5, 3, 12), Priority::Normal).await;
+        add_at(&pool, "D", at(2023, 5, 4, 12), Priority::Critical).await;
+        pool
+    }
+
+    async fn sorted(pool: &Pool<Sqlite>, sort: SortField, reversed: bool) -> Vec<String> {
+        get_entries(&Filters::default(), reversed, &sort, &[], None, pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| x.text)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn chronological_is_newest_first() {
+        let pool = interleaved_pool().await;
+        assert_eq!(
+            sorted(&pool, SortField::Date, false).await,
+            ["D", "C", "B", "A"]
+        );
+    }
+
+    #[tokio::test]
+    async fn chronological_reversed_is_oldest_first() {
+        let pool = interleaved_pool().await;
+        assert_eq!(
+            sorted(&pool, SortField::Date, true).await,
+            ["A", "B", "C", "D"]
+        );
+    }
+
+    #[tokio::test]
+    async fn by_priority_is_newest_first_within_each_priority() {
+        let pool = interleaved_pool().await;
+        assert_eq!(
+            sorted(&pool, SortField::Priority, false).await,
+            ["D", "B", "C", "A"]
+        );
+    }
+
+    #[tokio::test]
+    async fn by_priority_reversed_is_oldest_first_within_each_priority() {
+        let pool = interleaved_pool().await;
+        assert_eq!(
+            sorted(&pool, SortField::Priority, true).await,
+            ["B", "D", "A", "C"]
+        );
+    }
+
+    #[tokio::test]
+    async fn by_priority_follows_the_given_order() {
+        let pool = interleaved_pool().await;
+
+        let todos = get_entries(
+            &Filters::default(),
+            false,
+            &SortField::Priority,
+            &[Priority::Normal],
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(texts(&todos), ["C", "A", "D", "B"]);
+    }
+
+    #[tokio::test]
+    async fn first_entry_after_prune_is_one() {
+        let pool = memory_pool().await;
+        for text in ["A", "B", "C"] {
+            add_at(&pool, text, at(2023, 5, 1, 12), Priority::Normal).await;
+        }
+        delete_by_id(3, false, &pool).await.unwrap();
+
+        assert_eq!(prune(&pool).await.unwrap(), 3);
+
+        add_at(&pool, "D", at(2023, 5, 2, 12), Priority::Normal).await;
+        let todos = get_entries(
+            &Filters::default(),
+            false,
+            &SortField::Date,
+            &[],
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].id, 1);
+    }
+
+    #[tokio::test]
+    async fn undo_does_not_remove_an_occurrence_reusing_a_done_id() {
+        let pool = memory_pool().await;
+        post_todos(
+            &["Water plants".to_owned()],
+            &pool,
+            None,
+            Priority::Normal,
+            None,
+            &[],
+            Some(Recurrence::Daily),
+            &[],
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        match complete_by_id(1, &pool).await.unwrap() {
+            Completion::Recurred { id, .. } => assert_eq!(id, 1),
+            _ => panic!("The entry should have recurred."),
+        }
+
+        assert!(undo(&pool).await.unwrap().is_none());
+
+        let todos = get_entries(
+            &Filters::default(),
+            false,
+            &SortField::Date,
+            &[],
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(texts(&todos), ["Water plants"]);
+    }
+
+    #[tokio::test]
+    async fn two_pools_on_one_file_can_both_write() {
+        let path = env::temp_dir().join(format!("cltodo-wal-{}.db", std::process::id()));
+        let url = format!("sqlite://{}", path.display());
+
+        let first = get_connection_from_url(&url).await.unwrap();
+        let second = get_connection_from_url(&url).await.unwrap();
+
+        let writers = [first.clone(), second.clone()].map(|pool| {
+            tokio::spawn(async move {
+                for _ in 0..5 {
+                    add_at(&pool, "Concurrent", at(2023, 5, 1, 12), Priority::Normal).await;
+                }
+            })
+        });
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        let count = get_entries(
+            &Filters::default(),
+            false,
+            &SortField::Date,
+            &[],
+            None,
+            &first,
+        )
+        .await
+        .unwrap()
+        .len();
+
+        first.close().await;
+        second.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+
+        assert_eq!(count, 10);
+    }
+
+    #[tokio::test]
+    async fn entries_with_the_same_date_are_ordered_by_id() {
+        let pool = memory_pool().await;
+        let content = r#"[
+            {"text": "First", "priority": "normal", "date": "2023-05-01T12:00:00+00:00"},
+            {"text": "Second", "priority": "normal", "date": "2023-05-01T12:00:00+00:00"}
+        ]"#;
+        import_todos(parse_import(content).unwrap(), &pool)
+            .await
+            .unwrap();
+
+        for sort in [SortField::Date, SortField::Priority] {
+            assert_eq!(
+                sorted(&pool, sort.clone(), false).await,
+                ["Second", "First"]
+            );
+            assert_eq!(sorted(&pool, sort, true).await, ["First", "Second"]);
+        }
+    }
+}
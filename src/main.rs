@@ -1,423 +1,2512 @@
-use chrono::{DateTime, Local, NaiveDate, ParseError};
-use home::home_dir;
-use sqlx::{query, sqlite::SqlitePoolOptions, FromRow, Pool, QueryBuilder, Sqlite};
-use std::io::{self, Write};
-use std::path::PathBuf;
-use std::{
-    fs::{create_dir_all, OpenOptions},
-    process::Command,
-    str::FromStr,
-    time,
+mod tui;
+
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use cltodo::{
+    apply_replacements, archive_before, bump_by_id, check_blocker, check_subtask, commit_date,
+    complete_by_id, copy_by_id, count_by_day, count_entries, database_location, delete_by_id,
+    delete_by_ids, delete_entries, diagnose, edit_by_id, end_of_day, find_duplicate,
+    find_replacements, get_connection, get_due_entries, get_entries, get_entries_by_status,
+    get_stats, import_todos, is_quiet, move_by_id, open_database, pin_by_id, post_todos,
+    preview_archive_before, preview_delete_by_ids, preview_delete_entries, preview_prune, prune,
+    read_config, read_import, reprioritize, restore_by_id, search_entries, set_date_format,
+    set_quiet, set_utc_storage, start_of_day, to_datetime_from, to_datetime_to, to_tag, to_text,
+    undo, AppError, ColorConfig, Completion, Config, Extendable, Filters, Priority, Recurrence,
+    Relative, Scope, SortField, Stats, Status, Todo, STATS_DAYS,
 };
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::{env, process, time};
+
+use clap::builder::{BoolishValueParser, RangedU64ValueParser};
+use clap::{ArgAction, ArgGroup, Args, ColorChoice, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::{generate, Shell};
+use colored::{Color, ColoredString, Colorize};
+use log::LevelFilter;
+use rand::seq::SliceRandom;
+use regex::Regex;
+use terminal_size::{terminal_size, Width};
+
+/// Narrowest width the text of entries is wrapped to, however little room is left for it.
+const MIN_WRAP_WIDTH: usize = 20;
+
+/// Number of entries on each page when paginating without an explicit size.
+const DEFAULT_PER_PAGE: usize = 10;
+
+/// Widest bar of the histogram of entries added per day.
+const HISTOGRAM_WIDTH: i64 = 40;
+
+/// Indentation of the entries under the header of their group.
+const GROUP_INDENT: usize = 2;
+
+/// Exit code of a query that ran successfully but found no entries.
+const EXIT_NOTHING_FOUND: i32 = 1;
+
+/// Exit code of a command that failed.
+const EXIT_ERROR: i32 = 2;
+
+/// How a command that ran without errors ended, deciding the exit code.
+enum Outcome {
+    Success,
+    NothingFound,
+}
+
+impl Outcome {
+    /// Outcome of a query that found the given entries.
+    fn of<T>(results: &[T]) -> Self {
+        if results.is_empty() {
+            Outcome::NothingFound
+        } else {
+            Outcome::Success
+        }
+    }
+}
+
+/// Prints an informational message, such as the outcome of a change, unless running quietly.
+macro_rules! inform {
+    ($($arg:tt)*) => {
+        if !is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[tokio::main]
+async fn main() {
+    // Dates given as arguments may follow the format set in the config file, so it is read first.
+    // Errors in it are only reported once the command needs it.
+    let config = read_config();
+    set_date_format(config.as_ref().ok());
+
+    let args = Cli::parse();
+
+    let no_color_env = env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty());
+    match args.color {
+        _ if args.no_color => colored::control::set_override(false),
+        ColorChoice::Always => colored::control::set_override(true),
+        ColorChoice::Never => colored::control::set_override(false),
+        ColorChoice::Auto if no_color_env || !io::stdout().is_terminal() => {
+            colored::control::set_override(false)
+        }
+        ColorChoice::Auto => (),
+    }
+
+    set_utc_storage(args.utc);
+    set_quiet(args.quiet);
+    init_logging(args.verbose);
+
+    #[cfg(feature = "sqlcipher")]
+    if let Some(key) = args.key.clone() {
+        cltodo::set_db_key(key);
+    }
+
+    match run(args, config).await {
+        Ok(Outcome::Success) => (),
+        Ok(Outcome::NothingFound) => process::exit(EXIT_NOTHING_FOUND),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// Logs diagnostics to stderr according to the number of times `--verbose` was given.
+///
+/// Once, it logs how the todo list was found. Twice, it also logs the SQL that is run,
+/// and more also logs details from the database driver.
+fn init_logging(verbose: u8) {
+    let (own, sql) = match verbose {
+        0 => return,
+        1 => (LevelFilter::Info, LevelFilter::Off),
+        2 => (LevelFilter::Debug, LevelFilter::Info),
+        _ => (LevelFilter::Trace, LevelFilter::Trace),
+    };
+
+    env_logger::Builder::new()
+        .format_timestamp(None)
+        .filter_module("cltodo", own)
+        .filter_module("sqlx", sql)
+        .init();
+}
+
+/// Asks the user for confirmation through stdin, defaulting to no.
+fn confirm(prompt: &str) -> io::Result<bool> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Reads the text of an entry from stdin until end-of-input, trimming surrounding whitespace.
+fn read_text_from_stdin() -> Result<String, AppError> {
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text)?;
+
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(AppError::MissingText);
+    }
+
+    Ok(text.to_owned())
+}
+
+/// Reads the texts of several entries from stdin, one per line, trimming them and skipping blank lines.
+fn read_lines_from_stdin() -> Result<Vec<String>, AppError> {
+    let texts: Vec<String> = io::stdin()
+        .lines()
+        .map(|x| x.map(|x| x.trim().to_owned()))
+        .filter(|x| x.as_ref().map_or(true, |x| !x.is_empty()))
+        .collect::<Result<_, _>>()?;
+
+    if texts.is_empty() {
+        return Err(AppError::MissingText);
+    }
+
+    Ok(texts)
+}
+
+/// Runs the command selected through the CLI.
+async fn run(args: Cli, config: Result<Config, AppError>) -> Result<Outcome, AppError> {
+    let global = args.global;
+    let dry_run = args.dry_run;
+
+    // Completions do not depend on any todo list, so they are generated before connecting.
+    if let Some(Commands::Completions { shell }) = args.command {
+        let mut command = Cli::command();
+        let name = command.get_name().to_owned();
+        generate(shell, &mut command, name, &mut io::stdout());
+
+        return Ok(Outcome::Success);
+    }
+
+    // Flags take precedence over the config file, which takes precedence over the defaults.
+    let config = config?;
+
+    let list = match (args.list, config.list) {
+        (Some(x), _) => Some(x),
+        (None, Some(x)) => Some(to_list_name(&x).map_err(AppError::InvalidConfig)?),
+        (None, None) => None,
+    };
+
+    let priority_order = args
+        .priority_order
+        .or(config.priority_order)
+        .unwrap_or_default();
+
+    let pool = get_connection(global, args.project_root.as_deref(), list.as_deref()).await?;
+
+    let colors = ColorScheme::from_env(ColorScheme::from_config(&config.colors));
+
+    let default_display = DisplayOptions {
+        extended: config.extended,
+        ..Default::default()
+    };
+
+    // Running without a subcommand lists all entries.
+    let Some(mut command) = args.command else {
+        let results = get_entries(
+            &Filters::default(),
+            false,
+            &SortField::default(),
+            &priority_order,
+            None,
+            &pool,
+        )
+        .await?;
+        let outcome = Outcome::of(&results);
+        print_query_results(&mut io::stdout(), results, &default_display, &colors)?;
+
+        return Ok(outcome);
+    };
+
+    if dry_run && !command.supports_dry_run() {
+        return Err(AppError::DryRunUnsupported);
+    }
+
+    if let Commands::Get { display, .. }
+    | Commands::Today { display, .. }
+    | Commands::Search { display, .. } = &mut command
+    {
+        display.extended |= config.extended;
+    }
+
+    // Queries that find no entries end with their own exit code, for scripts to tell them apart.
+    let mut outcome = Outcome::Success;
+
+    match command {
+        Commands::Add {
+            text,
+            stdin,
+            many,
+            priority,
+            due,
+            tags,
+            repeat,
+            subtasks,
+            no_dup,
+            dup_since,
+            date,
+            blocked_by,
+            note,
+        } => {
+            let mut texts = match text {
+                _ if many => read_lines_from_stdin()?,
+                Some(x) => vec![x],
+                None if stdin || !io::stdin().is_terminal() => vec![read_text_from_stdin()?],
+                None => return Err(AppError::MissingText),
+            };
+
+            if no_dup {
+                let mut kept: Vec<String> = Vec::new();
+
+                for text in texts {
+                    if let Some(id) = find_duplicate(&text, dup_since, &pool).await? {
+                        eprintln!("Skipping duplicate of entry #{}: {}", id, text);
+                    } else if !kept.contains(&text) {
+                        kept.push(text);
+                    }
+                }
+
+                if kept.is_empty() {
+                    return Ok(Outcome::Success);
+                }
+                texts = kept;
+            }
+
+            let priority = priority.or(config.priority).unwrap_or(Priority::Normal);
+
+            if let Some(x) = blocked_by {
+                check_blocker(None, x, &pool).await?;
+            }
+
+            if dry_run {
+                let due = due
+                    .map(|x| format!(" (due {})", x.get_style(false)))
+                    .unwrap_or_default();
+                let tags = if tags.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", tags.join(", "))
+                };
+                let recurrence = repeat
+                    .map(|x| format!(" (repeats {})", x.name()))
+                    .unwrap_or_default();
+                let dated = date
+                    .map(|x| format!(" (dated {})", x.get_style(false)))
+                    .unwrap_or_default();
+                let blocked = blocked_by
+                    .map(|x| format!(" (blocked by #{})", x))
+                    .unwrap_or_default();
+
+                for text in &texts {
+                    println!(
+                        "Would add {} entry: {}{}{}{}{}{}",
+                        priority, text, tags, due, recurrence, dated, blocked
+                    );
+                    for subtask in &subtasks {
+                        println!("  - {}", subtask);
+                    }
+                    for line in note.iter().flat_map(|x| x.lines()) {
+                        println!("  {}", line);
+                    }
+                }
+
+                return Ok(Outcome::Success);
+            }
+
+            post_todos(
+                &texts,
+                &pool,
+                date,
+                priority,
+                due,
+                &tags,
+                repeat,
+                &subtasks,
+                blocked_by,
+                note.as_deref().filter(|x| !x.is_empty()),
+            )
+            .await?;
+
+            if many {
+                inform!("Added {} entries.", texts.len());
+            }
+        }
+        Commands::Get {
+            mut filters,
+            since_commit,
+            reversed,
+            mut display,
+            chronological,
+            sort,
+            limit,
+            oldest,
+            newest,
+            json,
+            json_lines,
+            porcelain,
+            format,
+            random,
+            weighted,
+            page,
+            per_page,
+            matches,
+            status,
+            count_only,
+            output,
+            watch,
+        } => {
+            // Files are not wrapped, nor their entries cut, to the width of the terminal.
+            if output.is_some() && display.wrap.is_none() {
+                display.wrap = Some(0);
+            }
+
+            // The oldest entry is the first one on the reversed order, and the newest one is
+            // the first on the default order, be it grouped by priority or purely chronological.
+            let (reversed, limit) = match (oldest, newest) {
+                (true, _) => (true, Some(1)),
+                (_, true) => (false, Some(1)),
+                _ => (reversed, limit),
+            };
+
+            let sort = if chronological { SortField::Date } else { sort };
+
+            if let Some(reference) = since_commit {
+                filters.from = Some(commit_date(&reference)?);
+            }
+
+            // Entries are only loaded when they must be matched against the regular expression,
+            // or when they are not all on the list.
+            if count_only {
+                let count = match (matches, &status) {
+                    (None, Scope::Active) => count_entries(&filters, &pool).await?.iter().sum(),
+                    (regex, _) => get_entries_by_status(
+                        &status,
+                        &filters,
+                        false,
+                        &SortField::Date,
+                        &[],
+                        None,
+                        &pool,
+                    )
+                    .await?
+                    .iter()
+                    .filter(|x| regex.as_ref().is_none_or(|r| r.is_match(&x.text)))
+                    .count() as i64,
+                };
+
+                writeln!(open_output(output.as_deref())?, "{}", count)?;
+                return Ok(if count == 0 {
+                    Outcome::NothingFound
+                } else {
+                    Outcome::Success
+                });
+            }
+
+            // Without --watch, the loop runs only once. Otherwise, the screen is cleared and the
+            // entries fetched again on every tick, until interrupted.
+            let mut interval = watch.map(|x| tokio::time::interval(time::Duration::from_secs(x)));
+
+            loop {
+                if let Some(interval) = &mut interval {
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        _ = tokio::signal::ctrl_c() => return Ok(Outcome::Success),
+                    }
+
+                    print!("\x1B[2J\x1B[H");
+                    println!(
+                        "Every {}s: {}\n",
+                        interval.period().as_secs(),
+                        Local::now().format("%Y-%m-%d %H:%M:%S")
+                    );
+                }
+
+                // The regular expression is matched after fetching, so the limit can only be
+                // applied once the entries are filtered.
+                let fetch_limit = if matches.is_some() { None } else { limit };
+                let mut results = get_entries_by_status(
+                    &status,
+                    &filters,
+                    reversed,
+                    &sort,
+                    &priority_order,
+                    fetch_limit,
+                    &pool,
+                )
+                .await?;
+
+                if let Some(regex) = &matches {
+                    results.retain(|x| regex.is_match(&x.text));
+
+                    if let Some(x) = limit {
+                        results.truncate(x);
+                    }
+                }
+
+                if random {
+                    let mut rng = rand::thread_rng();
+                    let chosen = if weighted {
+                        results
+                            .choose_weighted(&mut rng, |x| x.priority.to_i64() + 1)
+                            .ok()
+                    } else {
+                        results.choose(&mut rng)
+                    };
+
+                    results = chosen.cloned().into_iter().collect();
+                }
+
+                let mut footer = None;
+                if page.is_some() || per_page.is_some() {
+                    (results, footer) = paginate(
+                        results,
+                        page.unwrap_or(1),
+                        per_page.unwrap_or(DEFAULT_PER_PAGE),
+                    );
+                }
+
+                outcome = Outcome::of(&results);
+                let mut out = open_output(output.as_deref())?;
+
+                if json {
+                    print_json_results(&mut out, &results)?;
+                } else if json_lines {
+                    print_json_lines_results(&mut out, &results)?;
+                } else if porcelain {
+                    print_porcelain_results(&mut out, &results)?;
+                } else {
+                    if let Some(template) = &format {
+                        print_formatted_results(
+                            &mut out,
+                            results,
+                            template,
+                            display.extended,
+                            &colors,
+                        )?;
+                    } else {
+                        print_query_results(&mut out, results, &display, &colors)?;
+                    }
+
+                    if let Some(x) = footer {
+                        writeln!(out, "{}", x)?;
+                    }
+                }
+
+                if interval.is_none() {
+                    break;
+                }
+            }
+        }
+        Commands::Today {
+            priority,
+            display,
+            reversed,
+        } => {
+            let today = Local::now().date_naive();
+            let filters = Filters {
+                priority,
+                from: Some(start_of_day(today)),
+                to: Some(end_of_day(today)),
+                ..Default::default()
+            };
+
+            let results = get_entries(
+                &filters,
+                reversed,
+                &SortField::default(),
+                &priority_order,
+                None,
+                &pool,
+            )
+            .await?;
+            outcome = Outcome::of(&results);
+            print_query_results(&mut io::stdout(), results, &display, &colors)?;
+        }
+        Commands::Count { filters } => {
+            print_counts(count_entries(&filters, &pool).await?);
+        }
+        Commands::Stats {
+            histogram: true,
+            from,
+            to,
+        } => {
+            let to = to.unwrap_or_else(Local::now);
+            let from = from.unwrap_or_else(|| {
+                start_of_day((to - Duration::days(STATS_DAYS - 1)).date_naive())
+            });
+
+            print_histogram(&count_by_day(from, to, &pool).await?);
+        }
+        Commands::Stats { .. } => print_stats(get_stats(&pool).await?),
+        Commands::Doctor { fix } => {
+            let diagnosis = diagnose(fix && !dry_run, &pool).await?;
+
+            for (id, problem) in &diagnosis.problems {
+                println!("#{}: {}", id, problem);
+            }
+
+            println!(
+                "Checked {} entries, found {} malformed.",
+                diagnosis.checked,
+                diagnosis.problems.len()
+            );
+
+            if diagnosis.problems.is_empty() {
+                return Ok(Outcome::Success);
+            }
+
+            match (fix, dry_run) {
+                (true, true) => println!(
+                    "Would move {} entries to the malformed_todos table.",
+                    diagnosis.problems.len()
+                ),
+                (true, false) => inform!(
+                    "Moved {} entries to the malformed_todos table.",
+                    diagnosis.problems.len()
+                ),
+                (false, _) => inform!("Run with --fix to move them out of the list."),
+            }
+        }
+        Commands::Delete {
+            ids,
+            filters,
+            purge,
+            ..
+        } if dry_run => {
+            let entries = if ids.is_empty() {
+                preview_delete_entries(&filters, purge, &pool).await?
+            } else {
+                preview_delete_by_ids(&ids, purge, &pool).await?
+            };
+
+            print_dry_run("delete", entries, &colors)?;
+        }
+        Commands::Delete {
+            ids,
+            filters,
+            purge,
+            yes,
+        } => match ids.as_slice() {
+            [id] => {
+                if !delete_by_id(*id, purge, &pool).await? {
+                    return Err(AppError::EntryNotFound(*id));
+                }
+            }
+            [_, ..] => {
+                let mut ids = ids.clone();
+                ids.sort_unstable();
+                ids.dedup();
+
+                let deleted = delete_by_ids(&ids, purge, &pool).await?;
+
+                let missing: Vec<i64> = ids
+                    .iter()
+                    .filter(|x| !deleted.contains(x))
+                    .copied()
+                    .collect();
+
+                inform!("Deleted {} of {} entries.", deleted.len(), ids.len());
+                if !missing.is_empty() {
+                    return Err(AppError::EntriesNotFound(missing));
+                }
+            }
+            [] => {
+                // Purging also removes the entries already marked as deleted, which are not counted
+                // by `count_entries`.
+                let total = preview_delete_entries(&filters, purge, &pool).await?.len();
+
+                if total == 0 {
+                    inform!("No entries to delete.");
+                    return Ok(Outcome::Success);
+                }
+
+                let prompt = format!("This will delete {} entries. Continue? [y/N] ", total);
+                if yes || confirm(&prompt)? {
+                    let count = delete_entries(&filters, purge, &pool).await?;
+                    inform!("Deleted {} entries.", count);
+                } else {
+                    inform!("Aborted.");
+                }
+            }
+        },
+        Commands::Rename {
+            filters,
+            replace: (from, to),
+            yes,
+        } => {
+            let replacements = find_replacements(&filters, &from, &to, &pool).await?;
+
+            if replacements.is_empty() {
+                inform!("No entries contain {:?}.", from);
+                return Ok(Outcome::Success);
+            }
+
+            for x in &replacements {
+                println!("#{}: - {}", x.id, x.old);
+                println!("{:w$}  + {}", "", x.new, w = x.id.to_string().len() + 1);
+            }
+
+            if dry_run {
+                println!("Would rename {} entries.", replacements.len());
+                return Ok(Outcome::Success);
+            }
+
+            let prompt = format!("Rename {} entries? [y/N] ", replacements.len());
+            if yes || confirm(&prompt)? {
+                let count = apply_replacements(&replacements, &pool).await?;
+                inform!("Renamed {} entries.", count);
+            } else {
+                inform!("Aborted.");
+            }
+        }
+        Commands::Export { format, output } => {
+            let results = get_entries(
+                &Filters::default(),
+                false,
+                &SortField::default(),
+                &priority_order,
+                None,
+                &pool,
+            )
+            .await?;
+
+            let mut out = open_output(output.as_deref())?;
+
+            match format {
+                ExportFormat::Csv => print_csv_results(&mut out, &results)?,
+                ExportFormat::Json => print_json_results(&mut out, &results)?,
+                ExportFormat::JsonLines => print_json_lines_results(&mut out, &results)?,
+            }
+        }
+        Commands::Move {
+            id,
+            to_global,
+            to_project: _,
+        } => {
+            let root = args.project_root.as_deref();
+
+            // Outside a git repository, or with CLTODO_DB_URL set, both lists are the same
+            // database, whose lock would be held by the source while writing to the destination.
+            let from = database_location(!to_global, root, list.as_deref())?;
+            let to = database_location(to_global, root, list.as_deref())?;
+            if from.is_same(&to) {
+                return Err(AppError::SameList);
+            }
+
+            let source = open_database(&from).await?;
+            let destination = open_database(&to).await?;
+
+            match move_by_id(id, &source, &destination).await? {
+                Some(new_id) => inform!(
+                    "Moved entry {} to the {} list as #{}.",
+                    id,
+                    if to_global { "global" } else { "project" },
+                    new_id
+                ),
+                None => return Err(AppError::EntryNotFound(id)),
+            }
+        }
+        Commands::Archive { before } if dry_run => {
+            print_dry_run(
+                "archive",
+                preview_archive_before(before, &pool).await?,
+                &colors,
+            )?;
+        }
+        Commands::Archive { before } => {
+            let count = archive_before(before, &pool).await?;
+
+            inform!("Archived {} entries.", count);
+        }
+        Commands::Import { file } => {
+            let todos = read_import(&file)?;
+            let count = todos.len();
+
+            if dry_run {
+                println!("Would import {} entries.", count);
+                return Ok(Outcome::Success);
+            }
+
+            import_todos(todos, &pool).await?;
+
+            inform!("Imported {} entries.", count);
+        }
+        Commands::Search {
+            term,
+            display,
+            reversed,
+        } => {
+            let results = search_entries(&term, reversed, &pool).await?;
+            outcome = Outcome::of(&results);
+            print_query_results(&mut io::stdout(), results, &display, &colors)?;
+        }
+        Commands::Copy { id, text } => match copy_by_id(id, text.as_deref(), &pool).await? {
+            Some(new_id) => inform!("Copied entry #{} as #{}.", id, new_id),
+            None => return Err(AppError::EntryNotFound(id)),
+        },
+        Commands::Bump {
+            from_priority: Some(from),
+            to_priority: Some(to),
+            ..
+        } => {
+            let count = reprioritize(from.clone(), to.clone(), &pool).await?;
+            inform!("Changed {} entries from {} to {}.", count, from, to);
+        }
+        Commands::Bump { id: None, .. } => {
+            unreachable!("Either an id or both priorities should be required by the arguments.")
+        }
+        Commands::Bump {
+            id: Some(id), down, ..
+        } => {
+            let (old, new) = bump_by_id(id, down, &pool).await?;
+            inform!("Entry #{} bumped from {} to {}.", id, old, new);
+        }
+        Commands::Done { id } => match complete_by_id(id, &pool).await? {
+            Completion::NotFound => return Err(AppError::EntryNotFound(id)),
+            Completion::Done => (),
+            Completion::Recurred { id, due } => {
+                inform!(
+                    "Next occurrence added as #{}, due {}.",
+                    id,
+                    due.get_style(false)
+                );
+            }
+        },
+        Commands::Edit {
+            id,
+            text,
+            priority,
+            blocked_by,
+            unblock,
+            note,
+        } => {
+            if let Some(x) = blocked_by {
+                check_blocker(Some(id), x, &pool).await?;
+            }
+
+            let blocked_by = if unblock {
+                Some(None)
+            } else {
+                blocked_by.map(Some)
+            };
+
+            if !edit_by_id(
+                id,
+                text.as_deref(),
+                priority,
+                blocked_by,
+                note.as_deref(),
+                &pool,
+            )
+            .await?
+            {
+                return Err(AppError::EntryNotFound(id));
+            }
+        }
+        Commands::Pin { id } => {
+            if !pin_by_id(id, true, &pool).await? {
+                return Err(AppError::EntryNotFound(id));
+            }
+        }
+        Commands::Unpin { id } => {
+            if !pin_by_id(id, false, &pool).await? {
+                return Err(AppError::EntryNotFound(id));
+            }
+        }
+        Commands::Check { id, index } => match check_subtask(id, index, &pool).await? {
+            Some(subtask) => inform!(
+                "Subtask {} of entry #{} marked as {}: {}",
+                index,
+                id,
+                if subtask.done { "done" } else { "not done" },
+                subtask.text
+            ),
+            None => return Err(AppError::SubtaskNotFound(id, index)),
+        },
+        Commands::CheckDue { notify } => {
+            let notifier = match (notify, config.notify) {
+                (Some(x), _) => Some(x),
+                (None, Some(x)) => Some(to_notifier(&x).map_err(AppError::InvalidConfig)?),
+                (None, None) => None,
+            };
+
+            let today = Local::now().date_naive();
+            let entries = get_due_entries(end_of_day(today), &pool).await?;
+
+            let Some(Notifier(words)) = notifier else {
+                print_query_results(&mut io::stdout(), entries, &default_display, &colors)?;
+                return Ok(outcome);
+            };
+
+            for entry in &entries {
+                let words: Vec<String> = words.iter().map(|x| x.render(entry, false)).collect();
+
+                if dry_run {
+                    println!("Would run: {}", words.join(" "));
+                    continue;
+                }
+
+                let status = process::Command::new(&words[0]).args(&words[1..]).status();
+                match status {
+                    Ok(x) if x.success() => (),
+                    Ok(x) => eprintln!(
+                        "Warning: notifier for entry #{} failed with {}.",
+                        entry.id, x
+                    ),
+                    Err(e) => eprintln!(
+                        "Warning: could not run notifier for entry #{}: {}.",
+                        entry.id, e
+                    ),
+                }
+            }
+        }
+        Commands::Tui { priority } => {
+            if io::stdin().is_terminal() && io::stdout().is_terminal() {
+                let priority = priority.or(config.priority).unwrap_or(Priority::Normal);
+                tui::run(&pool, priority, &priority_order, &colors).await?;
+            } else {
+                if !is_quiet() {
+                    eprintln!("Note: not attached to a terminal, listing entries instead.");
+                }
+
+                let results = get_entries(
+                    &Filters::default(),
+                    false,
+                    &SortField::default(),
+                    &priority_order,
+                    None,
+                    &pool,
+                )
+                .await?;
+                print_query_results(&mut io::stdout(), results, &default_display, &colors)?;
+            }
+        }
+        Commands::Undo {} => match undo(&pool).await? {
+            Some((operation, id)) => inform!("Undid {} of entry #{}.", operation.name(), id),
+            None => inform!("Nothing to undo."),
+        },
+        Commands::Restore { id } => {
+            if !restore_by_id(id, &pool).await? {
+                return Err(AppError::DeletedEntryNotFound(id));
+            }
+        }
+        Commands::Completions { .. } => {
+            unreachable!("Completions should be generated before connecting.")
+        }
+        Commands::Prune { .. } if dry_run => {
+            print_dry_run("prune", preview_prune(&pool).await?, &colors)?;
+        }
+        Commands::Prune { yes } => {
+            // Entries marked as deleted are pruned too, so they are counted as well.
+            let total = preview_prune(&pool).await?.len();
+
+            if total == 0 {
+                inform!("No entries to prune.");
+                return Ok(Outcome::Success);
+            }
+
+            let prompt = format!("This will delete all {} entries. Continue? [y/N] ", total);
+            if yes || confirm(&prompt)? {
+                let count = prune(&pool).await?;
+                inform!("Pruned {} entries.", count);
+            } else {
+                inform!("Aborted.");
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+/// Opens the destination of the output of a command, i.e. the given file or else stdout.
+///
+/// Colors are disabled when writing to a file, so that it does not end up with escape codes.
+fn open_output(path: Option<&Path>) -> io::Result<Box<dyn Write>> {
+    match path {
+        Some(x) => {
+            colored::control::set_override(false);
+            Ok(Box::new(File::create(x)?))
+        }
+        None => Ok(Box::new(io::stdout())),
+    }
+}
+
+/// Prints the entries a command would affect, as given by a dry run.
+fn print_dry_run(action: &str, entries: Vec<Todo>, colors: &ColorScheme) -> io::Result<()> {
+    println!("Would {} {} entries.", action, entries.len());
+
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    print_query_results(
+        &mut io::stdout(),
+        entries,
+        &DisplayOptions::default(),
+        colors,
+    )
+}
+
+/// How the todo list in use is chosen, shown at the end of the help.
+const LOCATION_HELP: &str = "\
+The todo list in use is, from first to last choice:
+  1. the database at CLTODO_DB_URL, if set;
+  2. the global list, with --global;
+  3. the list in the directory given by --here;
+  4. the list of the current git repository, or else the global one.
+Named lists given by --list live alongside the chosen one.
+
+Exit codes:
+  0  success;
+  1  the query (get, today or search) found no entries;
+  2  an error occurred, including when no entry has a given id.";
+
+/// CLI Todo.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None, after_help = LOCATION_HELP)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Uses the global todo list, even inside a git repository.
+    #[arg(short, long, visible_alias = "no-project", default_value_t = false)]
+    global: bool,
+
+    /// Stores dates in UTC instead of the local timezone, e.g. to share lists between timezones.
+    /// They are still displayed in local time.
+    #[arg(long, env = "CLTODO_UTC", value_parser = BoolishValueParser::new())]
+    utc: bool,
+
+    /// Uses the todo list in the given directory, e.g. one that is not a git repository,
+    /// instead of detecting the project through git.
+    #[arg(
+        long,
+        visible_alias = "here",
+        value_name = "PATH",
+        conflicts_with = "global"
+    )]
+    project_root: Option<PathBuf>,
+
+    /// Uses the todo list with the given name, stored alongside the global or project one.
+    #[arg(long, value_parser = to_list_name)]
+    list: Option<String>,
+
+    /// When to color the output. With "auto", colors are disabled by the NO_COLOR environment
+    /// variable or when not writing to a terminal.
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Disables colored output. Same as `--color never`.
+    #[arg(long, default_value_t = false, conflicts_with = "color")]
+    no_color: bool,
+
+    /// Logs diagnostics to stderr, such as the database in use. Can be repeated, e.g. `-vv`
+    /// to also log the SQL that is run.
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Silences informational messages, such as the outcome of changes, keeping only results and errors.
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+
+    /// Passphrase with which the todo list is encrypted. It is needed every time the list
+    /// is opened, so it is best given through the environment variable.
+    #[cfg(feature = "sqlcipher")]
+    #[arg(long, env = "CLTODO_DB_KEY", hide_env_values = true)]
+    key: Option<String>,
+
+    /// Order in which entries are sorted by priority, e.g. "normal,important,critical".
+    /// Priorities left out come after the given ones.
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "PRIORITIES")]
+    priority_order: Option<Vec<Priority>>,
+
+    /// Shows what would change instead of changing it. Supported by add, delete, archive, import, prune, rename, check-due and doctor.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Add TODO entry.
+    Add {
+        /// Text describing the TODO task. Read from stdin if absent. Texts starting with a dash
+        /// are accepted unless they look like flags, in which case they can be given after `--`.
+        #[arg(allow_hyphen_values = true, value_parser = to_text)]
+        text: Option<String>,
+
+        /// Reads the text describing the TODO task from stdin.
+        #[arg(long, default_value_t = false, conflicts_with = "text")]
+        stdin: bool,
+
+        /// Reads several TODO tasks from stdin, one per line, adding them all at once.
+        #[arg(long, default_value_t = false, conflicts_with_all = ["text", "stdin"])]
+        many: bool,
+
+        /// Priority of the TODO task, by name or as 0, 1 or 2. Defaults to the one in the config file,
+        /// or else to normal.
+        #[arg(short, long, env = "CLTODO_DEFAULT_PRIORITY")]
+        priority: Option<Priority>,
+
+        /// Due date of the TODO task. If only a date is given, the task is due at the end of that day.
+        #[arg(short, long, value_parser = to_datetime_to, allow_hyphen_values = true)]
+        due: Option<DateTime<Local>>,
+
+        /// Tag categorizing the TODO task. Can be given multiple times.
+        #[arg(long = "tag", value_name = "TAG", value_parser = to_tag)]
+        tags: Vec<String>,
+
+        /// Repeats the TODO task, adding its next occurrence whenever it is marked as done.
+        #[arg(long)]
+        repeat: Option<Recurrence>,
+
+        /// Subtask of the TODO task, which can be checked with `check`. Can be given multiple times.
+        #[arg(long = "subtask", visible_alias = "subtasks", value_name = "TEXT")]
+        subtasks: Vec<String>,
+
+        /// Refuses to add the TODO task if an entry with the very same text already exists,
+        /// reporting its id instead.
+        #[arg(long, default_value_t = false)]
+        no_dup: bool,
+
+        /// Only considers entries added since the given date/datetime as duplicates.
+        #[arg(
+            long,
+            value_name = "DATE",
+            value_parser = to_datetime_from,
+            allow_hyphen_values = true,
+            requires = "no_dup"
+        )]
+        dup_since: Option<DateTime<Local>>,
+
+        /// Date of the TODO task, e.g. to backfill past ones. Defaults to now.
+        #[arg(long, value_parser = to_datetime_from, allow_hyphen_values = true)]
+        date: Option<DateTime<Local>>,
+
+        /// Id of the entry that must be done before this one.
+        #[arg(long, value_name = "ID")]
+        blocked_by: Option<i64>,
+
+        /// Longer description of the TODO task, shown under its text by `get --notes`.
+        #[arg(long, allow_hyphen_values = true)]
+        note: Option<String>,
+    },
+
+    /// Counts TODO entries based on the parameters, grouped by priority.
+    Count {
+        #[command(flatten)]
+        filters: Filters,
+    },
+
+    /// Summarizes the TODO entries.
+    Stats {
+        /// Shows a bar chart of how many entries were added each day instead.
+        #[arg(long, default_value_t = false)]
+        histogram: bool,
+
+        /// First day of the bar chart. Defaults to 30 days before the last one.
+        #[arg(
+            short,
+            long,
+            value_parser = to_datetime_from,
+            allow_hyphen_values = true,
+            requires = "histogram"
+        )]
+        from: Option<DateTime<Local>>,
+
+        /// Last day of the bar chart. Defaults to today.
+        #[arg(
+            short,
+            long,
+            value_parser = to_datetime_to,
+            allow_hyphen_values = true,
+            requires = "histogram"
+        )]
+        to: Option<DateTime<Local>>,
+    },
+
+    /// Checks that every TODO entry is well-formed, reporting the ones that are not.
+    Doctor {
+        /// Moves the malformed entries to the `malformed_todos` table, out of the list.
+        #[arg(long, default_value_t = false)]
+        fix: bool,
+    },
+
+    /// Delete TODO entries based on their ids, or all entries matching the filters.
+    /// They can be brought back with `restore`.
+    ///
+    /// Date bounds are inclusive: `--before <DATE>`, like `--to`, also deletes the entries
+    /// added on that day.
+    #[command(group(
+        ArgGroup::new("target")
+            .required(true)
+            .multiple(true)
+            .args(["ids", "priority", "priority_min", "from", "to", "tag"]),
+    ))]
+    Delete {
+        /// Ids of the entries to delete.
+        #[arg(
+            value_name = "ID",
+            conflicts_with_all = ["priority", "priority_min", "from", "to", "tag"]
+        )]
+        ids: Vec<i64>,
+
+        #[command(flatten)]
+        filters: Filters,
+
+        /// Removes the entries permanently instead.
+        #[arg(long, default_value_t = false)]
+        purge: bool,
+
+        /// Skips the confirmation prompt when deleting by filters.
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
+    },
+
+    /// Replaces a word across the text of all TODO entries matching the filters, e.g. to rename
+    /// a project. Shows the changes and asks for confirmation first.
+    Rename {
+        #[command(flatten)]
+        filters: Filters,
+
+        /// Text to replace and its replacement, as FROM=TO. Matched case-sensitively.
+        #[arg(long, value_name = "FROM=TO", value_parser = to_replacement)]
+        replace: (String, String),
+
+        /// Skips the confirmation prompt.
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
+    },
+
+    /// Exports all TODO entries to stdout in the given format.
+    Export {
+        /// Format of the exported entries.
+        #[arg(short, long)]
+        format: ExportFormat,
+
+        /// Writes the entries to the given file instead of stdout.
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// Archives TODO entries older than the given date, removing them from the list.
+    Archive {
+        /// Entries added before this date/datetime are archived. Exclusive.
+        #[arg(long, value_parser = to_datetime_from, allow_hyphen_values = true)]
+        before: DateTime<Local>,
+    },
+
+    /// Imports TODO entries from a JSON array, such as the one given by `export`.
+    Import {
+        /// Path of the JSON file to import. Use "-" to read from stdin.
+        file: PathBuf,
+    },
+
+    /// Moves TODO entry between the global and the project lists based on its id.
+    #[command(group(ArgGroup::new("destination").required(true)))]
+    Move {
+        id: i64,
+
+        /// Moves the entry from the project list to the global one.
+        #[arg(long, group = "destination")]
+        to_global: bool,
+
+        /// Moves the entry from the global list to the project one.
+        #[arg(long, group = "destination")]
+        to_project: bool,
+    },
+
+    /// Adds a copy of TODO entry based on its id, dated now.
+    Copy {
+        id: i64,
+
+        /// Text of the copy, instead of the text of the original entry.
+        #[arg(short, long, allow_hyphen_values = true, value_parser = to_text)]
+        text: Option<String>,
+    },
+
+    /// Raises the priority of TODO entry by one level based on its id, or changes the
+    /// priority of many entries at once.
+    Bump {
+        #[arg(required_unless_present = "from_priority")]
+        id: Option<i64>,
+
+        /// Lowers the priority instead of raising it.
+        #[arg(short, long, default_value_t = false)]
+        down: bool,
+
+        /// Changes the priority of every entry with the given one instead, to `--to-priority`.
+        #[arg(
+            long,
+            value_name = "PRIORITY",
+            requires = "to_priority",
+            conflicts_with_all = ["id", "down"]
+        )]
+        from_priority: Option<Priority>,
+
+        /// New priority of the entries with the priority given by `--from-priority`.
+        #[arg(long, value_name = "PRIORITY", requires = "from_priority")]
+        to_priority: Option<Priority>,
+    },
+
+    /// Mark TODO entry as done based on its id, moving it to the completed list.
+    Done { id: i64 },
+
+    /// Edit text, priority, blocker and/or note of TODO entry based on its id.
+    #[command(group(ArgGroup::new("fields").required(true).multiple(true)))]
+    Edit {
+        id: i64,
+
+        /// New text describing the TODO task.
+        #[arg(short, long, group = "fields", value_parser = to_text)]
+        text: Option<String>,
+
+        /// New priority of the TODO task.
+        #[arg(short, long, group = "fields")]
+        priority: Option<Priority>,
+
+        /// Id of the entry that must be done before this one.
+        #[arg(long, value_name = "ID", group = "fields")]
+        blocked_by: Option<i64>,
+
+        /// Removes the entry blocking this one.
+        #[arg(
+            long,
+            default_value_t = false,
+            group = "fields",
+            conflicts_with = "blocked_by"
+        )]
+        unblock: bool,
+
+        /// New note of the TODO task. An empty note removes it.
+        #[arg(long, group = "fields", allow_hyphen_values = true)]
+        note: Option<String>,
+    },
+
+    /// Queries TODO entries based on the parameters.
+    #[command(visible_alias = "list")]
+    Get {
+        #[command(flatten)]
+        filters: Filters,
+
+        /// Filters by entries added since the given git commit, tag or branch was committed.
+        /// Inclusive.
+        #[arg(long, value_name = "REF", conflicts_with = "from")]
+        since_commit: Option<String>,
+
+        #[command(flatten)]
+        display: DisplayOptions,
+
+        /// Reverses the order displayed on the query. The default is more recent entries on the top,
+        /// which still holds within each priority when grouping by it.
+        #[arg(short, long, default_value_t = false)]
+        reversed: bool,
+
+        /// Sticks to chronological order sort only, disregarding priority. Same as `--sort date`.
+        #[arg(short, long, default_value_t = false)]
+        chronological: bool,
+
+        /// Field by which entries are sorted.
+        #[arg(long, value_enum, default_value_t, conflicts_with = "chronological")]
+        sort: SortField,
+
+        /// Displays at most the given number of entries, after sorting.
+        #[arg(short, long)]
+        limit: Option<usize>,
+
+        /// Displays only the oldest entry, among the highest priority ones unless chronological.
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["newest", "reversed", "limit", "sort"]
+        )]
+        oldest: bool,
+
+        /// Displays only the newest entry, among the highest priority ones unless chronological.
+        #[arg(long, default_value_t = false, conflicts_with_all = ["reversed", "limit", "sort"])]
+        newest: bool,
+
+        /// Outputs the entries as a JSON array instead of styled text.
+        #[arg(long, default_value_t = false)]
+        json: bool,
+
+        /// Outputs each entry as a JSON object on its own line, e.g. for streaming to other tools.
+        #[arg(long, default_value_t = false, conflicts_with_all = ["json", "format"])]
+        json_lines: bool,
+
+        /// Outputs each entry on its own line as tab-separated fields, in a format that is kept
+        /// stable across versions for scripts: id, priority as 0 to 2, RFC 3339 date and text.
+        /// Tabs, newlines and backslashes in the text are escaped as `\t`, `\n` and `\\`.
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["json", "json_lines", "format"]
+        )]
+        porcelain: bool,
+
+        /// Displays each entry following the given template, e.g. "{id} {priority} {date} {text}".
+        /// Available placeholders are {id}, {priority}, {date}, {text}, {due} and {tags}.
+        #[arg(long, value_parser = to_template, conflicts_with_all = ["json", "table"])]
+        format: Option<Template>,
+
+        /// Displays a single entry chosen at random among those matching the filters.
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["limit", "oldest", "newest", "page", "per_page", "count_only"]
+        )]
+        random: bool,
+
+        /// Makes entries with higher priorities more likely to be chosen by `--random`.
+        #[arg(long, default_value_t = false, requires = "random")]
+        weighted: bool,
+
+        /// Filters by entries whose text matches the given regular expression.
+        #[arg(long, value_name = "REGEX", value_parser = to_regex)]
+        matches: Option<Regex>,
+
+        /// Looks into entries with the given status, e.g. `done` for those marked as done.
+        #[arg(long, value_enum, default_value_t = Scope::Active)]
+        status: Scope,
+
+        /// Displays the given page of entries, after sorting. Starts at 1.
+        #[arg(
+            long,
+            value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+            conflicts_with_all = ["limit", "oldest", "newest"]
+        )]
+        page: Option<usize>,
+
+        /// Number of entries on each page. Defaults to 10.
+        #[arg(
+            long,
+            value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+            conflicts_with_all = ["limit", "oldest", "newest"]
+        )]
+        per_page: Option<usize>,
+
+        /// Writes the output to the given file instead of stdout, without colors.
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Prints only the number of entries matching the filters.
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with_all = [
+                "limit", "oldest", "newest", "page", "per_page", "json", "json_lines", "porcelain",
+                "format"
+            ]
+        )]
+        count_only: bool,
+
+        /// Clears the screen and displays the entries again every given number of seconds,
+        /// 5 by default, until interrupted with Ctrl-C.
+        #[arg(
+            long,
+            value_name = "SECS",
+            num_args = 0..=1,
+            default_missing_value = "5",
+            value_parser = RangedU64ValueParser::<u64>::new().range(1..),
+            conflicts_with_all = ["output", "count_only"]
+        )]
+        watch: Option<u64>,
+    },
+
+    /// Searches TODO entries whose text contains the given term, case-insensitively.
+    Search {
+        /// Term to look for in the text of the TODO tasks.
+        term: String,
+
+        #[command(flatten)]
+        display: DisplayOptions,
+
+        /// Reverses the order displayed on the query. The default is more recent entries on the top.
+        #[arg(short, long, default_value_t = false)]
+        reversed: bool,
+    },
+
+    /// Queries TODO entries added today.
+    Today {
+        /// Filters by entries with the given priority. Can be given multiple times, or as a
+        /// comma-separated list, to match any of them.
+        #[arg(short, long, value_delimiter = ',')]
+        priority: Vec<Priority>,
+
+        #[command(flatten)]
+        display: DisplayOptions,
+
+        /// Reverses the order displayed on the query. The default is more recent entries on the top,
+        /// which still holds within each priority.
+        #[arg(short, long, default_value_t = false)]
+        reversed: bool,
+    },
+
+    /// Notifies about TODO entries that are overdue or due today, e.g. from a cron job.
+    ///
+    /// Runs the notifier once per entry, or lists the entries if no notifier is configured.
+    /// Exits with code 0 even when nothing is due, so that cron and systemd do not report it as
+    /// a failure.
+    CheckDue {
+        /// Command run for each entry, e.g. "notify-send {text}". Placeholders are the same as
+        /// for `get --format`. Defaults to the `notify` setting of the config file.
+        #[arg(long, value_name = "CMD", value_parser = to_notifier)]
+        notify: Option<Notifier>,
+    },
+
+    /// Browses and manages TODO entries through an interactive interface.
+    Tui {
+        /// Priority of the entries added through the interface, by name or as 0, 1 or 2. Defaults
+        /// to the one in the config file, or else to normal.
+        #[arg(short, long, env = "CLTODO_DEFAULT_PRIORITY")]
+        priority: Option<Priority>,
+    },
+
+    /// Pins TODO entry based on its id, listing it before the other entries whatever their priority.
+    Pin { id: i64 },
+
+    /// Unpins TODO entry based on its id.
+    Unpin { id: i64 },
+
+    /// Toggles whether a subtask of TODO entry is done based on its id and the subtask position.
+    Check {
+        id: i64,
+
+        /// Position of the subtask, starting at 1.
+        index: usize,
+    },
+
+    /// Undoes the last add, delete, edit, bump, check or pin. Can be repeated to go further back.
+    Undo {},
+
+    /// Restores a deleted TODO entry based on its id.
+    Restore { id: i64 },
+
+    /// Prints a completion script for the given shell, e.g. `cltodo completions zsh > _cltodo`.
+    Completions { shell: Shell },
+
+    /// Prunes all entries, also resetting ids.
+    Prune {
+        /// Skips the confirmation prompt.
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
+    },
+}
+
+impl Commands {
+    /// Whether the command can run with `--dry-run`, either because it previews its changes
+    /// or because it does not change anything.
+    fn supports_dry_run(&self) -> bool {
+        !matches!(
+            self,
+            Commands::Move { .. }
+                | Commands::Copy { .. }
+                | Commands::Bump { .. }
+                | Commands::Done { .. }
+                | Commands::Edit { .. }
+                | Commands::Tui { .. }
+                | Commands::Pin { .. }
+                | Commands::Unpin { .. }
+                | Commands::Check { .. }
+                | Commands::Undo {}
+                | Commands::Restore { .. }
+        )
+    }
+}
+
+/// Compiles a regular expression.
+fn to_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| e.to_string())
+}
+
+/// Parses a replacement given as FROM=TO, splitting on the first '='.
+fn to_replacement(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some(("", _)) => Err("The text to replace cannot be empty.".to_string()),
+        Some((from, to)) => Ok((from.to_string(), to.to_string())),
+        None => Err("Expected FROM=TO.".to_string()),
+    }
+}
+
+/// Validates a list name, which must be usable as a folder name.
+fn to_list_name(s: &str) -> Result<String, String> {
+    if s.is_empty() || s == "." || s == ".." {
+        Err("Invalid list name.".to_string())
+    } else if s.contains(['/', '\\']) {
+        Err("List names cannot contain path separators.".to_string())
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+/// Field of an entry that can be placed on a template.
+#[derive(Clone)]
+enum Placeholder {
+    Id,
+    Priority,
+    Date,
+    Text,
+    Due,
+    Tags,
+}
+
+/// Piece of a template, either kept as is or replaced by a field of each entry.
+#[derive(Clone)]
+enum Segment {
+    Literal(String),
+    Field(Placeholder),
+}
+
+/// Template describing how to display each entry.
+#[derive(Clone)]
+struct Template(Vec<Segment>);
+
+impl Template {
+    /// Fills the template with the fields of the given entry.
+    fn render(&self, todo: &Todo, extended: bool) -> String {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(x) => x.clone(),
+                Segment::Field(Placeholder::Id) => todo.id.to_string(),
+                Segment::Field(Placeholder::Priority) => todo.priority.to_string(),
+                Segment::Field(Placeholder::Date) => todo.date.get_style(extended),
+                Segment::Field(Placeholder::Text) => todo.text.clone(),
+                Segment::Field(Placeholder::Due) => {
+                    todo.due.map(|x| x.get_style(extended)).unwrap_or_default()
+                }
+                Segment::Field(Placeholder::Tags) => todo.tags.join(","),
+            })
+            .collect()
+    }
+}
 
-use clap::{Parser, Subcommand, ValueEnum};
-use colored::Colorize;
+/// Command run for each entry, given as a program followed by its arguments, each being
+/// a template. Arguments are passed as they are, without going through a shell.
+#[derive(Clone)]
+struct Notifier(Vec<Template>);
 
-const DB_FOLDER: &str = ".cltodo";
-const DB_FILE: &str = "data.db";
+/// Parses a notifier, splitting the program and its arguments on whitespace.
+fn to_notifier(s: &str) -> Result<Notifier, String> {
+    let words = s
+        .split_whitespace()
+        .map(to_template)
+        .collect::<Result<Vec<_>, _>>()?;
 
-#[tokio::main]
-async fn main() -> Result<(), sqlx::Error> {
-    let args = Cli::parse();
+    if words.is_empty() {
+        return Err("The notifier command cannot be empty.".to_string());
+    }
 
-    let global = args.global;
+    Ok(Notifier(words))
+}
 
-    let pool = get_connection(global).await?;
+/// Parses a template, where placeholders are enclosed in braces and `{{`, `}}` stand for literal braces.
+fn to_template(s: &str) -> Result<Template, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = s.chars().peekable();
 
-    let query = sqlx::query!(
-        "CREATE TABLE IF NOT EXISTS todos (
-            id INTEGER PRIMARY KEY,
-            date TEXT NOT NULL,
-            text TEXT NOT NULL,
-            priority INTEGER NOT NULL
-        ) STRICT"
-    );
-    query.execute(&pool).await?;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(x) => name.push(x),
+                        None => return Err("Unclosed placeholder.".to_string()),
+                    }
+                }
 
-    match args.command {
-        Commands::Add { text, priority } => post_todo(&text, &pool, priority).await?,
-        Commands::Get {
-            priority,
-            from,
-            to,
-            reversed,
-            extended,
-            chronological,
-        } => {
-            print_query_results(
-                get_entries(priority, from, to, reversed, chronological, &pool).await?,
-                extended,
-            );
+                let placeholder = match name.as_str() {
+                    "id" => Placeholder::Id,
+                    "priority" => Placeholder::Priority,
+                    "date" => Placeholder::Date,
+                    "text" => Placeholder::Text,
+                    "due" => Placeholder::Due,
+                    "tags" => Placeholder::Tags,
+                    _ => return Err(format!("Unknown placeholder {{{}}}.", name)),
+                };
+
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Field(placeholder));
+            }
+            '}' => return Err("Unmatched '}'. Use '}}' for a literal brace.".to_string()),
+            x => literal.push(x),
         }
-        Commands::Delete { id } => delete_by_id(id, &pool).await?,
-        Commands::Prune {} => prune(&pool).await?,
     }
-    Ok(())
+
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+
+    Ok(Template(segments))
 }
 
-/// CLI Todo.
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-struct Cli {
-    #[command(subcommand)]
-    command: Commands,
+#[derive(Debug, ValueEnum, Clone)]
+enum ExportFormat {
+    Csv,
+    Json,
 
-    /// Uses the global todo list instead of project-specific ones.
-    #[arg(short, long, default_value_t = false)]
-    global: bool,
+    /// One JSON object per line.
+    JsonLines,
 }
 
-#[derive(Subcommand)]
-enum Commands {
-    /// Add TODO entry.
-    Add {
-        /// Text describing the TODO task.
-        text: String,
+/// Options on how to display entries shared by the commands that print them.
+#[derive(Args, Default)]
+struct DisplayOptions {
+    /// Displays datetimes in extended mode, i.e. with hours, mins, secs and time zone.
+    /// Also displays notes, as `--notes`.
+    #[arg(short, long, default_value_t = false)]
+    extended: bool,
 
-        /// Priority of the TODO task.
-        #[arg(short, long)]
-        priority: Priority,
-    },
+    /// Displays the notes of entries, indented under their text.
+    #[arg(long, default_value_t = false)]
+    notes: bool,
 
-    /// Delete TODO entry based on its id.
-    Delete { id: i64 },
+    /// Displays when entries were added relative to now, e.g. "3d ago", instead of their date.
+    #[arg(long, default_value_t = false)]
+    relative: bool,
 
-    /// Queries TODO entries based on the parameters.
-    Get {
-        /// Filters by entries with the given priority.
-        #[arg(short, long)]
-        priority: Option<Priority>,
+    /// Displays entries as a table, aligning all of its columns.
+    #[arg(long, default_value_t = false)]
+    table: bool,
 
-        /// Filters by entries that are more recent than the given datetime. Inclusive.
-        #[arg(short, long, value_parser = to_datetime_from)]
-        from: Option<DateTime<Local>>,
+    /// Wraps the text of entries at the given number of columns. Defaults to the width
+    /// of the terminal, and 0 disables wrapping.
+    #[arg(long, value_name = "COLS")]
+    wrap: Option<usize>,
 
-        /// Filters by entries that are older than the given datetime. Inclusive.
-        #[arg(short, long, value_parser = to_datetime_to)]
-        to: Option<DateTime<Local>>,
+    /// Displays a header with the color of each priority and how many of the entries have it.
+    #[arg(long, visible_alias = "summary", default_value_t = false)]
+    header: bool,
 
-        /// Displays datetimes in extended mode, i.e. with hours, mins, secs and time zone.
-        #[arg(short, long, default_value_t = false)]
-        extended: bool,
+    /// Displays priorities as symbols, from "!" for normal to "!!!" for critical, without padding.
+    #[arg(long, default_value_t = false)]
+    compact: bool,
 
-        /// Reverses the order displayed on the query. The default is more recent entries on the top.
-        #[arg(short, long, default_value_t = false)]
-        reversed: bool,
+    /// Groups entries under a header line for each value of the given field, keeping their
+    /// order within each group. Entries with several tags are shown under each of them.
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
 
-        /// Sticks to chronological order sort only, disregarding priority.
-        #[arg(short, long, default_value_t = false)]
-        chronological: bool,
-    },
+    /// Displays each entry on a single line, without its date, truncating the text to the
+    /// width of the terminal.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["table", "wrap", "group_by"])]
+    oneline: bool,
 
-    /// Prunes all entries, also resetting ids.
-    Prune {},
+    /// Displays only the text of each entry, without any decoration, e.g. to pipe it elsewhere.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["table", "wrap", "group_by", "header", "compact", "oneline"]
+    )]
+    text_only: bool,
+
+    /// Displays only the id of each entry, one per line, e.g. to pass them to `delete`.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = [
+            "table", "wrap", "group_by", "header", "compact", "oneline", "text_only", "relative"
+        ]
+    )]
+    id_only: bool,
+
+    /// Cuts the text of each entry to the given number of characters, ending it with an ellipsis.
+    #[arg(
+        long,
+        value_name = "CHARS",
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with_all = ["text_only", "id_only"]
+    )]
+    max_text_width: Option<usize>,
 }
 
-/// Transforms string to datetime.
-///
-/// If string is in date format, then sets hours, mins and secs to 0.
-fn to_datetime_from(s: &str) -> Result<DateTime<Local>, String> {
-    if let Ok(x) = DateTime::from_str(s) {
-        Ok(x)
-    } else if let Ok(x) = NaiveDate::from_str(s) {
-        let date_with_hms = x
-            .and_hms_opt(0, 0, 0)
-            .expect("All zeroes should be valid inputs.");
-        Ok(date_with_hms.and_local_timezone(Local).unwrap())
-    } else {
-        Err("Invalid input for date/datetime.".to_string())
+/// Field by which displayed entries are grouped.
+#[derive(Debug, ValueEnum, Clone)]
+enum GroupBy {
+    Priority,
+
+    /// Day the entry was added.
+    Day,
+
+    Tag,
+}
+
+impl DisplayOptions {
+    /// Renders the date an entry was added, either relative to now or in the chosen style.
+    fn date(&self, date: &DateTime<Local>) -> String {
+        if self.relative {
+            date.get_relative(Local::now())
+        } else {
+            date.get_style(self.extended)
+        }
+    }
+
+    /// Renders the priority either by name or, in compact mode, by symbol.
+    fn priority(&self, priority: &Priority) -> String {
+        if self.compact {
+            priority.symbol().to_string()
+        } else {
+            priority.to_string()
+        }
     }
 }
 
-/// Transforms string to datetime.
+/// Colors used to display entries of each priority.
 ///
-/// If string is in date format, then sets hours, min and secs to 11, 59 and 59 respectively.
-fn to_datetime_to(s: &str) -> Result<DateTime<Local>, String> {
-    if let Ok(x) = DateTime::from_str(s) {
-        Ok(x)
-    } else if let Ok(date_with_hms) = NaiveDate::from_str(s) {
-        let oi = date_with_hms
-            .and_hms_opt(11, 59, 59)
-            .expect("11, 59, 59 should be valid inputs.");
-        Ok(oi.and_local_timezone(Local).unwrap())
-    } else {
-        Err("Invalida input for date/datetime.".to_string())
-    }
+/// `None` stands for the default color of the terminal.
+struct ColorScheme {
+    critical: Option<Color>,
+    important: Option<Color>,
+    normal: Option<Color>,
 }
 
-trait Extendable {
-    fn get_style(&self, extended: bool) -> String;
+impl Default for ColorScheme {
+    fn default() -> Self {
+        ColorScheme {
+            critical: Some(Color::Red),
+            important: Some(Color::Yellow),
+            normal: None,
+        }
+    }
 }
 
-impl Extendable for DateTime<Local> {
-    /// Prints extended or non-extended mode.
+impl ColorScheme {
+    /// Reads the colors given by the config file, falling back to the default scheme
+    /// for the missing or invalid ones.
+    fn from_config(config: &ColorConfig) -> Self {
+        let default = ColorScheme::default();
+
+        let read = |name: &Option<String>, fallback: Option<Color>| match name {
+            Some(x) => parse_color(x, fallback),
+            None => fallback,
+        };
+
+        ColorScheme {
+            critical: read(&config.critical, default.critical),
+            important: read(&config.important, default.important),
+            normal: read(&config.normal, default.normal),
+        }
+    }
+
+    /// Reads the colors from the `CLTODO_COLOR_<PRIORITY>` environment variables.
     ///
-    /// Extended mode consists of entire ISO timestamp, whereas non-extended
-    /// consists of only the date (i.e., YYYY-MM-DD).
-    fn get_style(&self, extended: bool) -> String {
-        if extended {
-            self.to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
-        } else {
-            self.date_naive().to_string()
+    /// Unset or invalid colors fall back to the given scheme.
+    fn from_env(base: ColorScheme) -> Self {
+        let read = |var: &str, fallback: Option<Color>| match env::var(var) {
+            Ok(x) => parse_color(&x, fallback),
+            Err(_) => fallback,
+        };
+
+        ColorScheme {
+            critical: read("CLTODO_COLOR_CRITICAL", base.critical),
+            important: read("CLTODO_COLOR_IMPORTANT", base.important),
+            normal: read("CLTODO_COLOR_NORMAL", base.normal),
+        }
+    }
+
+    /// Returns the color of the given priority.
+    fn get(&self, priority: &Priority) -> Option<Color> {
+        match priority {
+            Priority::Critical => self.critical,
+            Priority::Important => self.important,
+            Priority::Normal => self.normal,
         }
     }
 }
 
-#[derive(Debug, ValueEnum, Clone)]
-enum Priority {
-    Normal = 0,
-    Important = 1,
-    Critical = 2,
+/// Parses a color by the names known by `colored`, as well as "none" for the default color
+/// of the terminal, falling back to the given color if invalid.
+fn parse_color(name: &str, fallback: Option<Color>) -> Option<Color> {
+    if name.eq_ignore_ascii_case("none") {
+        None
+    } else {
+        name.parse().ok().or(fallback)
+    }
 }
 
-impl Priority {
-    fn from_i64(i: i64) -> Result<Self, ()> {
-        match i {
-            0 => Ok(Priority::Normal),
-            1 => Ok(Priority::Important),
-            2 => Ok(Priority::Critical),
-            _ => Err(()),
+/// Prints results from queries with specific stylings.
+///
+/// In table mode, each column is padded to its widest value, which requires
+/// measuring all entries before printing them.
+fn print_query_results(
+    out: &mut impl Write,
+    results: Vec<Todo>,
+    display: &DisplayOptions,
+    colors: &ColorScheme,
+) -> io::Result<()> {
+    // Nothing is printed for an empty result set, so that scripts do not read the message as an entry.
+    if display.text_only || display.id_only {
+        let mut handle = io::BufWriter::new(out);
+
+        for result in &results {
+            if display.id_only {
+                writeln!(handle, "{}", result.id)?;
+            } else {
+                writeln!(handle, "{}", result.text)?;
+            }
         }
+
+        return handle.flush();
     }
-}
 
-impl ToString for Priority {
-    fn to_string(&self) -> String {
-        match self {
-            Priority::Normal => "NORMAL".to_string(),
-            Priority::Important => "IMPORTANT".to_string(),
-            Priority::Critical => "CRITICAL".to_string(),
+    if results.is_empty() {
+        return writeln!(out, "No results found.");
+    }
+
+    if display.header {
+        print_header(out, &results, display, colors)?;
+    }
+
+    if display.oneline {
+        return print_oneline_results(out, &results, display, colors);
+    }
+
+    let rows: Vec<[String; 5]> = results
+        .iter()
+        .map(|result| {
+            let due = result
+                .due
+                .map(|x| format!(" (due {})", x.get_style(display.extended)))
+                .unwrap_or_default();
+
+            let tags = if result.tags.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", result.tags.join(", "))
+            };
+
+            let progress = if result.subtasks.is_empty() {
+                String::new()
+            } else {
+                let done = result.subtasks.iter().filter(|x| x.done).count();
+                format!(" [{}/{}]", done, result.subtasks.len())
+            };
+
+            let recurrence = result
+                .recurrence
+                .as_ref()
+                .map(|x| format!(" (repeats {})", x.name()))
+                .unwrap_or_default();
+
+            let updated = if display.extended && result.updated_at != result.date {
+                format!(" (updated {})", result.updated_at.get_style(true))
+            } else {
+                String::new()
+            };
+
+            let blocked = result
+                .blocked_by
+                .map(|x| format!(" (blocked by #{})", x))
+                .unwrap_or_default();
+
+            let status = match result.status {
+                Status::Active => "",
+                Status::Done => " (done)",
+                Status::Archived => " (archived)",
+            };
+
+            [
+                format!("#{}{}", result.id, if result.pinned { "*" } else { "" }),
+                display.priority(&result.priority),
+                display.date(&result.date),
+                match display.max_text_width {
+                    Some(x) => truncate(&result.text, x),
+                    None => result.text.clone(),
+                },
+                progress + &tags + &due + &recurrence + &blocked + status + &updated,
+            ]
+        })
+        .collect();
+
+    let mut widths = [0, if display.compact { 0 } else { 9 }, 0, 0, 0];
+    if display.table {
+        for (i, width) in widths.iter_mut().enumerate().take(3) {
+            *width = rows.iter().map(|x| x[i].chars().count()).max().unwrap_or(0);
+        }
+    }
+
+    let wrap = display
+        .wrap
+        .or_else(|| terminal_size().map(|(Width(x), _)| x.into()))
+        .filter(|x| *x > 0);
+
+    let indent = if display.group_by.is_some() {
+        GROUP_INDENT
+    } else {
+        0
+    };
+
+    // Width taken by the indentation and the id, priority and date columns, including separators.
+    let offsets: Vec<usize> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(widths)
+                .take(3)
+                .map(|(x, width)| x.chars().count().max(width) + 2)
+                .sum::<usize>()
+                + indent
+        })
+        .collect();
+
+    let texts: Vec<Vec<String>> = rows
+        .iter()
+        .zip(&offsets)
+        .map(|(row, offset)| match wrap {
+            Some(x) => wrap_text(&row[3], x.saturating_sub(*offset).max(MIN_WRAP_WIDTH)),
+            None => vec![row[3].clone()],
+        })
+        .collect();
+
+    // The text is only padded when followed by tags or due dates, to avoid trailing spaces.
+    if display.table && rows.iter().any(|x| !x[4].is_empty()) {
+        widths[3] = texts
+            .iter()
+            .flatten()
+            .map(|x| x.chars().count())
+            .max()
+            .unwrap_or(0);
+    }
+
+    let groups = match &display.group_by {
+        Some(x) => group_entries(&results, x, display),
+        None => vec![(None, (0..results.len()).collect())],
+    };
+
+    let mut handle = io::BufWriter::new(out);
+
+    for (key, indices) in groups {
+        if let Some(x) = key {
+            writeln!(handle, "{}", x.bold())?;
+        }
+
+        for i in indices {
+            let result = &results[i];
+            let paint = |s: &str| paint(s, result, colors);
+
+            let [id, priority, date, _, extra] = &rows[i];
+            let lines = &texts[i];
+            let offset = offsets[i];
+
+            write!(
+                handle,
+                "{:indent$}{:<w0$}: {:<w1$}: {:<w2$}: ",
+                "",
+                paint(id),
+                paint(priority),
+                paint(date),
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+            )?;
+
+            // Continuation lines are indented to stay under the text column.
+            for (i, line) in lines.iter().enumerate() {
+                if i > 0 {
+                    write!(handle, "\n{:offset$}", "")?;
+                }
+
+                if i + 1 == lines.len() {
+                    write!(handle, "{:<w3$}", paint(line), w3 = widths[3])?;
+                } else {
+                    write!(handle, "{}", paint(line))?;
+                }
+            }
+
+            if !extra.is_empty() {
+                write!(handle, "{}", paint(extra))?;
+            }
+
+            writeln!(handle)?;
+
+            // Notes are wrapped like the text, and indented under it.
+            if let Some(note) = &result.note {
+                if display.extended || display.notes {
+                    let width =
+                        wrap.map_or(usize::MAX, |x| x.saturating_sub(offset).max(MIN_WRAP_WIDTH));
+
+                    for line in wrap_text(note, width) {
+                        writeln!(handle, "{:offset$}{}", "", line.dimmed())?;
+                    }
+                }
+            }
         }
     }
+
+    handle.flush()
 }
 
-#[derive(Debug, FromRow)]
-struct TodoEntry {
-    id: i64,
-    date: String,
-    text: String,
-    priority: i64,
+/// Prints entries as `#id [PRIORITY] text`, one per line, cutting the text with an ellipsis
+/// where it would overflow the terminal.
+fn print_oneline_results(
+    out: &mut impl Write,
+    results: &[Todo],
+    display: &DisplayOptions,
+    colors: &ColorScheme,
+) -> io::Result<()> {
+    let width = display
+        .wrap
+        .or_else(|| terminal_size().map(|(Width(x), _)| x.into()))
+        .filter(|x| *x > 0);
+
+    let mut handle = io::BufWriter::new(out);
+
+    for result in results {
+        let priority = if display.compact {
+            result.priority.symbol()
+        } else {
+            result.priority.short_name()
+        };
+        let prefix = format!(
+            "#{}{} [{}] ",
+            result.id,
+            if result.pinned { "*" } else { "" },
+            priority
+        );
+
+        let text = result.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let room = width.map(|x| x.saturating_sub(prefix.chars().count()));
+
+        let text = match room.into_iter().chain(display.max_text_width).min() {
+            Some(x) => truncate(&text, x),
+            None => text,
+        };
+
+        writeln!(handle, "{}", paint(&(prefix + &text), result, colors))?;
+    }
+
+    handle.flush()
 }
 
-#[derive(Debug, Clone)]
-struct Todo {
-    id: i64,
-    date: DateTime<Local>,
-    text: String,
-    priority: Priority,
+/// Cuts the text to the given number of characters, ending it with an ellipsis if it was cut.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_owned();
+    }
+
+    let kept: String = text.chars().take(width.saturating_sub(1)).collect();
+    kept + "…"
 }
 
-impl Todo {
-    /// Transforms TodoEntry into Todo.
-    fn from_entry(entry: &TodoEntry) -> Result<Self, ParseError> {
-        Ok(Todo {
-            id: entry.id,
-            date: DateTime::from_str(&entry.date)?,
-            text: entry.text.to_owned(),
-            priority: Priority::from_i64(entry.priority).expect("Expected integer from 0 to 2."),
-        })
+/// Groups the positions of the entries by the given field, in order of first appearance.
+///
+/// Entries keep their order within each group, and untagged entries are grouped together
+/// after the tags when grouping by tag.
+fn group_entries(
+    results: &[Todo],
+    group_by: &GroupBy,
+    display: &DisplayOptions,
+) -> Vec<(Option<String>, Vec<usize>)> {
+    let mut groups: Vec<(Option<String>, Vec<usize>)> = Vec::new();
+    let mut untagged = Vec::new();
+
+    for (i, result) in results.iter().enumerate() {
+        let keys = match group_by {
+            GroupBy::Priority => vec![display.priority(&result.priority)],
+            GroupBy::Day => vec![result.date.date_naive().to_string()],
+            GroupBy::Tag if result.tags.is_empty() => {
+                untagged.push(i);
+                continue;
+            }
+            GroupBy::Tag => result.tags.clone(),
+        };
+
+        for key in keys {
+            match groups.iter_mut().find(|(x, _)| x.as_ref() == Some(&key)) {
+                Some((_, indices)) => indices.push(i),
+                None => groups.push((Some(key), vec![i])),
+            }
+        }
+    }
+
+    if !untagged.is_empty() {
+        groups.push((Some("(untagged)".to_string()), untagged));
     }
+
+    groups
 }
 
-/// Posts new TODO into database.
-async fn post_todo(text: &str, pool: &Pool<Sqlite>, priority: Priority) -> Result<(), sqlx::Error> {
-    let now = time::SystemTime::now();
-    let to_store = DateTime::<Local>::from(now).to_string();
-    let priority = priority as i64;
+/// Prints a legend of the priority colors, each followed by how many of the entries have it.
+fn print_header(
+    out: &mut impl Write,
+    results: &[Todo],
+    display: &DisplayOptions,
+    colors: &ColorScheme,
+) -> io::Result<()> {
+    let mut counts = [0; 3];
+    for result in results {
+        counts[result.priority.to_i64() as usize] += 1;
+    }
 
-    let oi = sqlx::query!(
-        "INSERT INTO todos (date, text, priority) VALUES (?, ?, ?)",
-        to_store,
-        text,
-        priority
-    );
+    let legend: Vec<String> = [Priority::Critical, Priority::Important, Priority::Normal]
+        .iter()
+        .map(|x| {
+            let name = match colors.get(x) {
+                Some(color) => display.priority(x).color(color),
+                None => display.priority(x).normal(),
+            };
 
-    oi.execute(pool).await?;
+            format!("{}: {}", name, counts[x.to_i64() as usize])
+        })
+        .collect();
 
-    Ok(())
+    writeln!(
+        out,
+        "{} ({} overdue)",
+        legend.join(", "),
+        "underlined".bold().underline()
+    )
 }
 
-/// Gets entries from TODO list according to parameters selected.
-async fn get_entries(
-    priority: Option<Priority>,
-    from: Option<DateTime<Local>>,
-    to: Option<DateTime<Local>>,
-    reversed: bool,
-    chronological: bool,
-    pool: &Pool<Sqlite>,
-) -> Result<Vec<Todo>, sqlx::Error> {
-    let mut query = QueryBuilder::new("SELECT * from todos WHERE 1=1");
+/// Splits text into lines of at most the given width, breaking at word boundaries.
+///
+/// Line breaks already in the text are kept, and words longer than the width are split.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut length = 0;
+
+        for word in paragraph.split_whitespace() {
+            let mut chars: Vec<char> = word.chars().collect();
+
+            if length > 0 && length + 1 + chars.len() > width {
+                lines.push(std::mem::take(&mut line));
+                length = 0;
+            }
 
-    if let Some(x) = priority {
-        query.push(" AND priority = ");
-        query.push_bind(x as i64);
+            while chars.len() > width {
+                lines.push(chars.drain(..width).collect());
+            }
+
+            if length > 0 {
+                line.push(' ');
+                length += 1;
+            }
+
+            line.extend(&chars);
+            length += chars.len();
+        }
+
+        lines.push(line);
     }
 
-    if let Some(x) = from {
-        query.push(" AND date >= ");
-        query.push_bind(x.to_rfc3339());
+    lines
+}
+
+/// Prints entries following the given template, one per line.
+fn print_formatted_results(
+    out: &mut impl Write,
+    results: Vec<Todo>,
+    template: &Template,
+    extended: bool,
+    colors: &ColorScheme,
+) -> io::Result<()> {
+    if results.is_empty() {
+        return writeln!(out, "No results found.");
     }
 
-    if let Some(x) = to {
-        query.push(" AND date <= ");
-        query.push_bind(x.to_rfc3339());
+    let mut handle = io::BufWriter::new(out);
+
+    for result in &results {
+        let line = template.render(result, extended);
+
+        writeln!(handle, "{}", paint(&line, result, colors))?;
     }
 
-    if reversed {
-        query.push(" ORDER BY date ASC");
+    handle.flush()
+}
+
+/// Colors the text according to the priority of the entry, emphasizing it if overdue.
+fn paint(s: &str, todo: &Todo, colors: &ColorScheme) -> ColoredString {
+    let s = match colors.get(&todo.priority) {
+        Some(color) => s.color(color),
+        None => s.normal(),
+    };
+
+    if todo.is_overdue() {
+        s.bold().underline()
     } else {
-        query.push(" ORDER BY date DESC");
+        s
     }
+}
 
-    let query = query.build();
+/// Prints the summary of a TODO list.
+fn print_stats(stats: Stats) {
+    let counts = stats.counts;
+    let format_date = |x: Option<DateTime<Local>>| {
+        x.map(|x| x.get_style(false))
+            .unwrap_or_else(|| "-".to_string())
+    };
 
-    let entries: Vec<TodoEntry> = query
-        .fetch_all(pool)
-        .await?
-        .iter()
-        .map(|x| TodoEntry::from_row(x).expect("Database entries should always be convertible."))
-        .collect();
+    println!("Total:     {}", counts.iter().sum::<i64>());
+    println!("Critical:  {}", counts[Priority::Critical as usize]);
+    println!("Important: {}", counts[Priority::Important as usize]);
+    println!("Normal:    {}", counts[Priority::Normal as usize]);
+    println!("Oldest:    {}", format_date(stats.oldest));
+    println!("Newest:    {}", format_date(stats.newest));
+    println!(
+        "Added per day over the last {} days: {:.2}",
+        STATS_DAYS,
+        stats.recently_added as f64 / STATS_DAYS as f64
+    );
+}
 
-    let mut todos: Vec<Todo> = entries
-        .iter()
-        .map(|x| Todo::from_entry(x).expect("TodoEntries should always be convert to Todo."))
-        .collect();
+/// Prints how many entries were added on each day as a bar chart, one day per line.
+///
+/// Bars are scaled down to fit `HISTOGRAM_WIDTH` when some day has more entries than that.
+fn print_histogram(counts: &[(NaiveDate, i64)]) {
+    let max = counts.iter().map(|(_, x)| *x).max().unwrap_or(0);
 
-    if !chronological {
-        todos = todos
-            .iter()
-            .filter(|x| matches!(x.priority, Priority::Critical))
-            .chain(
-                todos
-                    .iter()
-                    .filter(|x| matches!(x.priority, Priority::Important))
-                    .chain(
-                        todos
-                            .iter()
-                            .filter(|x| matches!(x.priority, Priority::Normal)),
-                    ),
-            )
-            .cloned()
-            .collect();
+    for (day, count) in counts {
+        let length = if max > HISTOGRAM_WIDTH {
+            count * HISTOGRAM_WIDTH / max
+        } else {
+            *count
+        };
+
+        println!("{} {} {}", day, "█".repeat(length as usize), count);
+    }
+}
+
+/// Keeps only the entries on the given page, returning them alongside a footer describing the page.
+///
+/// Pages past the last one are clamped to it, with a warning.
+fn paginate(results: Vec<Todo>, page: usize, per_page: usize) -> (Vec<Todo>, Option<String>) {
+    let total = results.len();
+    if total == 0 {
+        return (results, None);
     }
 
-    Ok(todos)
+    let pages = total.div_ceil(per_page);
+    let page = if page > pages {
+        eprintln!(
+            "Warning: page {} is out of range, showing page {} instead.",
+            page, pages
+        );
+        pages
+    } else {
+        page
+    };
+
+    let results: Vec<Todo> = results
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .collect();
+
+    let footer = format!(
+        "Page {}/{} (showing {} of {})",
+        page,
+        pages,
+        results.len(),
+        total
+    );
+
+    (results, Some(footer))
+}
+
+/// Prints a summary of entry counts, indexed by priority value.
+fn print_counts(counts: [i64; 3]) {
+    println!(
+        "Critical: {}, Important: {}, Normal: {}, Total: {}",
+        counts[Priority::Critical as usize],
+        counts[Priority::Important as usize],
+        counts[Priority::Normal as usize],
+        counts.iter().sum::<i64>()
+    );
 }
 
-/// Deletes a database row via its id.
-async fn delete_by_id(id: i64, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
-    let q = query!("DELETE FROM todos WHERE id = ?", id);
+/// Prints results from queries as CSV, with a header row.
+fn print_csv_results(out: &mut impl Write, results: &[Todo]) -> io::Result<()> {
+    let mut handle = io::BufWriter::new(out);
 
-    q.execute(pool).await?;
+    writeln!(handle, "id,date,text,priority,due,tags")?;
 
-    Ok(())
+    for result in results {
+        writeln!(
+            handle,
+            "{},{},{},{},{},{}",
+            result.id,
+            result.date.to_rfc3339(),
+            escape_csv(&result.text),
+            result
+                .priority
+                .to_possible_value()
+                .expect("Priorities should always have a value name.")
+                .get_name(),
+            result.due.map(|x| x.to_rfc3339()).unwrap_or_default(),
+            escape_csv(&result.tags.join(","))
+        )?;
+    }
+
+    handle.flush()
 }
 
-/// Deletes all entries of database, also resetting the ids.
-async fn prune(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
-    let q = query!("DELETE FROM todos");
+/// Escapes a CSV field, quoting it if it contains commas, quotes or line breaks.
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
 
-    q.execute(pool).await?;
+/// Prints results from queries as JSON objects, one per line, flushing after each of them.
+fn print_json_lines_results(out: &mut impl Write, results: &[Todo]) -> io::Result<()> {
+    for result in results {
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string(result).expect("Todos should always be serializable.")
+        )?;
+        out.flush()?;
+    }
 
     Ok(())
 }
 
-/// Prints results from queries with specific stylings.
-fn print_query_results(results: Vec<Todo>, extended: bool) {
-    if results.is_empty() {
-        println!("No results found.");
-        return;
+/// Prints results from queries as tab-separated lines of id, priority value, RFC 3339 date
+/// and text, without colors nor any other formatting.
+///
+/// This format is meant for scripts, so its fields must never be changed nor reordered.
+fn print_porcelain_results(out: &mut impl Write, results: &[Todo]) -> io::Result<()> {
+    let mut handle = io::BufWriter::new(out);
+
+    for result in results {
+        let text = result
+            .text
+            .replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n");
+
+        writeln!(
+            handle,
+            "{}\t{}\t{}\t{}",
+            result.id,
+            result.priority.to_i64(),
+            result.date.to_rfc3339(),
+            text
+        )?;
     }
 
-    let stdout = io::stdout();
-    let mut handle = io::BufWriter::new(stdout.lock());
+    handle.flush()
+}
 
-    for result in results {
-        match result.priority {
-            Priority::Critical => writeln!(
-                handle,
-                "{}{}: {:<9}: {}: {}",
-                "#".red(),
-                result.id.to_string().red(),
-                result.priority.to_string().red(),
-                result.date.get_style(extended).red(),
-                result.text.red()
-            )
-            .expect("There should be no problems writing to stdout."),
-            Priority::Important => writeln!(
-                handle,
-                "{}{}: {:<9}: {}: {}",
-                "#".yellow(),
-                result.id.to_string().yellow(),
-                result.priority.to_string().yellow(),
-                result.date.get_style(extended).to_string().yellow(),
-                result.text.yellow()
-            )
-            .expect("There should be no problems writing to stdout."),
-            Priority::Normal => writeln!(
-                handle,
-                "{}{}: {:<9}: {}: {}",
-                "#",
-                result.id.to_string(),
-                result.priority.to_string(),
-                result.date.get_style(extended),
-                result.text
-            )
-            .expect("There should be no problems writing to stdout."),
+/// Prints results from queries as a JSON array.
+///
+/// An empty result set is printed as `[]`, so that the output is always valid JSON.
+fn print_json_results(out: &mut impl Write, results: &[Todo]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{}",
+        serde_json::to_string(results).expect("Todos should always be serializable.")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cltodo::get_connection_from_url;
+
+    fn parsed_text(args: &[&str]) -> Option<String> {
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Commands::Add { text, .. }) => text,
+            _ => panic!("expected an add command"),
         }
     }
-}
 
-/// Returns a pool of connections to the sqlite database.
-async fn get_connection(global: bool) -> Result<Pool<Sqlite>, sqlx::Error> {
-    let cltodo_folder = if global {
-        home_dir()
-            .expect("Home directory should be accessible.")
-            .join(DB_FOLDER)
-    } else if let Ok(output) = Command::new("git")
-        .arg("rev-parse")
-        .arg("--show-toplevel")
-        .output()
-    {
-        PathBuf::from(
-            std::str::from_utf8(&output.stdout)
-                .expect("Should have utf8 output.")
-                .trim(),
+    /// Stores a single entry with the given text, returning the text read back from the list.
+    async fn stored_text(text: String) -> String {
+        let pool = get_connection_from_url("sqlite::memory:").await.unwrap();
+
+        post_todos(
+            &[text],
+            &pool,
+            None,
+            Priority::Normal,
+            None,
+            &[],
+            None,
+            &[],
+            None,
+            None,
         )
-        .join(DB_FOLDER)
-    } else {
-        home_dir()
-            .expect("Home directory should be accessible.")
-            .join(DB_FOLDER)
-    };
-    println!("{:?}", cltodo_folder);
+        .await
+        .unwrap();
 
-    create_dir_all(&cltodo_folder).unwrap_or_else(|_| {
-        panic!(
-            "It should be possible to create the {} directory",
-            DB_FOLDER
+        let todos = get_entries(
+            &Filters::default(),
+            false,
+            &SortField::Date,
+            &[],
+            None,
+            &pool,
         )
-    });
+        .await
+        .unwrap();
+
+        todos[0].text.clone()
+    }
+
+    #[test]
+    fn cli_is_valid() {
+        Cli::command().debug_assert();
+    }
 
-    let data_file = cltodo_folder.join(DB_FILE);
-    let database_url = data_file
-        .to_str()
-        .expect("Data file path should be convertible to string.")
-        .to_owned();
+    #[test]
+    fn texts_starting_with_a_dash_are_accepted() {
+        assert_eq!(
+            parsed_text(&["cltodo", "add", "-fix the thing"]).as_deref(),
+            Some("-fix the thing")
+        );
+        assert_eq!(
+            parsed_text(&["cltodo", "add", "--", "-p is a flag"]).as_deref(),
+            Some("-p is a flag")
+        );
+    }
 
-    let database_url = database_url.trim_start_matches("\\\\?\\");
+    #[tokio::test]
+    async fn texts_starting_with_a_dash_are_stored_verbatim() {
+        let text = parsed_text(&["cltodo", "add", "-fix the thing"]).unwrap();
 
-    let creation = OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(database_url);
+        assert_eq!(stored_text(text).await, "-fix the thing");
+    }
 
-    if creation.is_ok() {
-        println!("Database file created at {}", database_url)
+    #[test]
+    fn blank_texts_are_rejected() {
+        assert!(Cli::try_parse_from(["cltodo", "add", ""]).is_err());
+        assert!(Cli::try_parse_from(["cltodo", "add", "   "]).is_err());
     }
 
-    SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(&format!("sqlite:///{}", database_url))
-        .await
+    #[test]
+    fn oldest_and_newest_conflict_with_sort() {
+        for flag in ["--oldest", "--newest"] {
+            assert!(Cli::try_parse_from(["cltodo", "get", flag, "--sort", "length"]).is_err());
+            assert!(Cli::try_parse_from(["cltodo", "get", flag, "--chronological"]).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn texts_are_stored_trimmed() {
+        let text = parsed_text(&["cltodo", "add", "  x  "]).unwrap();
+
+        assert_eq!(stored_text(text).await, "x");
+    }
 }
@@ -0,0 +1,277 @@
+//! Minimal full-screen interface to browse and manage a TODO list.
+
+use cltodo::{
+    bump_by_id, delete_by_id, get_entries, post_todo, AppError, Extendable, Filters, Priority,
+    SortField, Todo,
+};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use sqlx::{Pool, Sqlite};
+
+use crate::ColorScheme;
+
+const HELP: &str = "↑/↓: move  a: add  d: delete  +/-: bump  q: quit";
+
+/// What the keys currently act on.
+enum Mode {
+    /// Navigating through the entries.
+    Browsing,
+
+    /// Typing the text of a new entry.
+    Adding(String),
+}
+
+/// State of the interface.
+struct App<'a> {
+    entries: Vec<Todo>,
+    default_priority: Priority,
+    priority_order: &'a [Priority],
+    list: ListState,
+    mode: Mode,
+    status: String,
+}
+
+/// Runs the interface until the user quits, restoring the terminal afterwards.
+///
+/// Entries added through it get the given default priority.
+pub async fn run(
+    pool: &Pool<Sqlite>,
+    default_priority: Priority,
+    priority_order: &[Priority],
+    colors: &ColorScheme,
+) -> Result<(), AppError> {
+    let mut terminal = ratatui::init();
+    let result = run_app(
+        &mut terminal,
+        pool,
+        default_priority,
+        priority_order,
+        colors,
+    )
+    .await;
+    ratatui::restore();
+
+    result
+}
+
+async fn run_app(
+    terminal: &mut DefaultTerminal,
+    pool: &Pool<Sqlite>,
+    default_priority: Priority,
+    priority_order: &[Priority],
+    colors: &ColorScheme,
+) -> Result<(), AppError> {
+    let mut app = App {
+        entries: Vec::new(),
+        default_priority,
+        priority_order,
+        list: ListState::default(),
+        mode: Mode::Browsing,
+        status: HELP.to_string(),
+    };
+
+    app.reload(pool).await?;
+
+    loop {
+        terminal.draw(|frame| draw(frame, &mut app, colors))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match &mut app.mode {
+            Mode::Browsing => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') => app.list.select_next(),
+                KeyCode::Up | KeyCode::Char('k') => app.list.select_previous(),
+                KeyCode::Char('a') => {
+                    app.mode = Mode::Adding(String::new());
+                }
+                KeyCode::Char('d') => {
+                    if let Some(id) = app.selected_id() {
+                        delete_by_id(id, false, pool).await?;
+                        app.status = format!("Deleted entry #{}.", id);
+                        app.reload(pool).await?;
+                    }
+                }
+                KeyCode::Char(c @ ('+' | '-')) => {
+                    if let Some(id) = app.selected_id() {
+                        app.status = match bump_by_id(id, c == '-', pool).await {
+                            Ok((old, new)) => {
+                                format!("Entry #{} bumped from {} to {}.", id, old, new)
+                            }
+                            Err(AppError::PriorityAtLimit(id, old)) => {
+                                format!("Entry #{} is already {}.", id, old)
+                            }
+                            Err(e) => return Err(e),
+                        };
+                        app.reload(pool).await?;
+                        app.select(id);
+                    }
+                }
+                _ => (),
+            },
+            Mode::Adding(text) => match key.code {
+                KeyCode::Esc => {
+                    app.mode = Mode::Browsing;
+                    app.status = HELP.to_string();
+                }
+                KeyCode::Enter => {
+                    let text = std::mem::take(text);
+                    app.mode = Mode::Browsing;
+
+                    if text.trim().is_empty() {
+                        app.status = HELP.to_string();
+                    } else {
+                        post_todo(
+                            &text,
+                            pool,
+                            app.default_priority.clone(),
+                            None,
+                            &[],
+                            None,
+                            &[],
+                        )
+                        .await?;
+                        app.status = "Entry added.".to_string();
+                        app.reload(pool).await?;
+                    }
+                }
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                _ => (),
+            },
+        }
+    }
+}
+
+impl App<'_> {
+    /// Fetches the entries again, keeping the selection within bounds.
+    async fn reload(&mut self, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        self.entries = get_entries(
+            &Filters::default(),
+            false,
+            &SortField::default(),
+            self.priority_order,
+            None,
+            pool,
+        )
+        .await?;
+
+        let selected = match (self.list.selected(), self.entries.len()) {
+            (_, 0) => None,
+            (Some(x), len) => Some(x.min(len - 1)),
+            (None, _) => Some(0),
+        };
+        self.list.select(selected);
+
+        Ok(())
+    }
+
+    fn selected_id(&self) -> Option<i64> {
+        self.list
+            .selected()
+            .and_then(|x| self.entries.get(x))
+            .map(|x| x.id)
+    }
+
+    /// Selects the entry with the given id, if it is listed.
+    fn select(&mut self, id: i64) {
+        if let Some(i) = self.entries.iter().position(|x| x.id == id) {
+            self.list.select(Some(i));
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App, colors: &ColorScheme) {
+    let [list_area, status_area] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+
+    let items: Vec<ListItem> = app
+        .entries
+        .iter()
+        .map(|x| {
+            let line = format!(
+                "#{}: {:<9}: {}: {}",
+                x.id,
+                x.priority,
+                x.date.get_style(false),
+                x.text.replace('\n', " ")
+            );
+
+            ListItem::new(Line::styled(line, style(x, colors)))
+        })
+        .collect();
+
+    let list = if items.is_empty() {
+        List::new([ListItem::new("No results found.")])
+    } else {
+        List::new(items)
+    }
+    .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+    frame.render_stateful_widget(list, list_area, &mut app.list);
+
+    let status = match &app.mode {
+        Mode::Browsing => Paragraph::new(app.status.as_str()),
+        Mode::Adding(text) => {
+            let prompt = format!("New entry: {}", text);
+            frame.set_cursor_position((
+                status_area.x + prompt.chars().count() as u16,
+                status_area.y,
+            ));
+            Paragraph::new(prompt)
+        }
+    };
+
+    frame.render_widget(status, status_area);
+}
+
+/// Style of an entry, following the same colors as the regular output.
+fn style(todo: &Todo, colors: &ColorScheme) -> Style {
+    let mut style = Style::new();
+
+    if colored::control::SHOULD_COLORIZE.should_colorize() {
+        if let Some(color) = colors.get(&todo.priority) {
+            style = style.fg(to_tui_color(color));
+        }
+    }
+
+    if todo.is_overdue() {
+        style = style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+    }
+
+    style
+}
+
+fn to_tui_color(color: colored::Color) -> Color {
+    match color {
+        colored::Color::Black => Color::Black,
+        colored::Color::Red => Color::Red,
+        colored::Color::Green => Color::Green,
+        colored::Color::Yellow => Color::Yellow,
+        colored::Color::Blue => Color::Blue,
+        colored::Color::Magenta => Color::Magenta,
+        colored::Color::Cyan => Color::Cyan,
+        colored::Color::White => Color::Gray,
+        colored::Color::BrightBlack => Color::DarkGray,
+        colored::Color::BrightRed => Color::LightRed,
+        colored::Color::BrightGreen => Color::LightGreen,
+        colored::Color::BrightYellow => Color::LightYellow,
+        colored::Color::BrightBlue => Color::LightBlue,
+        colored::Color::BrightMagenta => Color::LightMagenta,
+        colored::Color::BrightCyan => Color::LightCyan,
+        colored::Color::BrightWhite => Color::White,
+        colored::Color::TrueColor { r, g, b } => Color::Rgb(r, g, b),
+    }
+}